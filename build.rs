@@ -0,0 +1,20 @@
+use std::{env, fs, path::PathBuf};
+
+fn main() {
+    // the linker script for the chip selected with the `rp235x` or `rp2040` feature, `link.x` includes it as `memory.x`
+    let memory = if env::var_os("CARGO_FEATURE_RP2040").is_some() {
+        "memory-rp2040.x"
+    } else {
+        "memory-rp235x.x"
+    };
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy(memory, out.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed={memory}");
+
+    // defmt keeps its format strings in a section that is placed by its own linker script
+    if env::var_os("CARGO_FEATURE_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+    }
+}
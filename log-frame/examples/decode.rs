@@ -0,0 +1,45 @@
+// Prints the frames read from a file, e.g. a capture or the serial port, or from stdin as text:
+// cargo run -p log-frame --example decode --target x86_64-unknown-linux-gnu -- /dev/ttyACM0
+
+use std::{
+    env, fs,
+    io::{self, Read},
+};
+
+use log_frame::{Decoder, Frame};
+
+fn main() -> io::Result<()> {
+    let mut input: Box<dyn Read> = match env::args().nth(1) {
+        Some(path) => Box::new(fs::File::open(path)?),
+        None => Box::new(io::stdin()),
+    };
+
+    let mut decoder = Decoder::new();
+    let mut buf = [0; 256];
+    loop {
+        let len = input.read(&mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+
+        decoder.feed(&buf[..len], |frame| match frame {
+            Ok(Frame::Log(record)) => {
+                let tag = record
+                    .task
+                    .map(|task| format!(" #{task}"))
+                    .unwrap_or_default();
+                println!(
+                    "[{}.{:03} {:<5} {}{}] {}",
+                    record.uptime_ms / 1000,
+                    record.uptime_ms % 1000,
+                    format!("{:?}", record.level).to_uppercase(),
+                    record.module,
+                    tag,
+                    record.message
+                );
+            }
+            Ok(Frame::Shell(line)) => println!("{line}"),
+            Err(e) => eprintln!("broken frame: {e}"),
+        });
+    }
+}
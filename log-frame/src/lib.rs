@@ -0,0 +1,312 @@
+#![cfg_attr(not(test), no_std)]
+
+// The framed binary log format sent over the usb serial port when the firmware is built with the `log-frames` feature.
+//
+// Every frame is a postcard encoded `Frame` followed by a little endian CRC-32 of those bytes, COBS encoded and terminated
+// by a zero byte. A corrupted or dropped byte only loses the frame it belongs to because the next zero byte always starts
+// a new frame. This crate is `no_std` so that the firmware and host tools share the same code.
+
+use core::fmt;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use serde::{Deserialize, Serialize};
+
+// the largest frame on the wire including the COBS overhead and the terminating zero
+pub const MAX_FRAME_LEN: usize = 512;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+const CRC_LEN: usize = 4;
+// COBS adds one byte for every 254 bytes plus one, and the frame ends with a zero
+const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - 2 - MAX_FRAME_LEN / 254 - CRC_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    // the frame does not fit into `MAX_FRAME_LEN` bytes
+    TooLong,
+    // invalid COBS encoding
+    Encoding,
+    // the frame is too short or its CRC does not match, e.g. because bytes were dropped
+    Checksum,
+    // the CRC matches but the contents cannot be decoded, e.g. the firmware uses a newer format
+    Format,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLong => write!(f, "frame longer than {MAX_FRAME_LEN} bytes"),
+            Self::Encoding => f.write_str("invalid COBS encoding"),
+            Self::Checksum => f.write_str("checksum mismatch"),
+            Self::Format => f.write_str("invalid frame contents"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord<'a> {
+    pub uptime_ms: u64,
+    pub level: Level,
+    pub module: &'a str,
+    // the log target when it is not the module, see the log format section of the readme
+    pub task: Option<&'a str>,
+    pub message: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Frame<'a> {
+    #[serde(borrow)]
+    Log(LogRecord<'a>),
+    // one line of the reply to a command typed into the serial shell
+    Shell(&'a str),
+}
+
+// Encodes `frame` into `buf`, returns the bytes to send including the terminating zero
+pub fn encode<'b>(frame: &Frame<'_>, buf: &'b mut [u8]) -> Result<&'b [u8], Error> {
+    let mut payload = [0; MAX_PAYLOAD_LEN + CRC_LEN];
+    let len = postcard::to_slice(frame, &mut payload[..MAX_PAYLOAD_LEN])
+        .map_err(|_| Error::TooLong)?
+        .len();
+    let crc = CRC.checksum(&payload[..len]);
+    payload[len..len + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+
+    let len = cobs::try_encode(&payload[..len + CRC_LEN], buf).map_err(|_| Error::TooLong)?;
+    *buf.get_mut(len).ok_or(Error::TooLong)? = 0;
+    Ok(&buf[..=len])
+}
+
+// Decodes a single COBS encoded frame without its terminating zero. The frame is decoded in place.
+pub fn decode(frame: &mut [u8]) -> Result<Frame<'_>, Error> {
+    let len = cobs::decode_in_place(frame).map_err(|_| Error::Encoding)?;
+    if len < CRC_LEN {
+        return Err(Error::Checksum);
+    }
+
+    let (payload, crc) = frame[..len].split_at(len - CRC_LEN);
+    if CRC.checksum(payload).to_le_bytes() != crc {
+        return Err(Error::Checksum);
+    }
+
+    postcard::from_bytes(payload).map_err(|_| Error::Format)
+}
+
+// Splits a byte stream, e.g. read from the serial port, into frames
+pub struct Decoder {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    overflow: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    // Calls `on_frame` for every frame completed by `data`. The bytes before the first zero byte can be the tail
+    // of a frame sent before the host started reading, they are reported as an error like any other broken frame.
+    pub fn feed(&mut self, data: &[u8], mut on_frame: impl FnMut(Result<Frame<'_>, Error>)) {
+        for &b in data {
+            if b != 0 {
+                match self.buf.get_mut(self.len) {
+                    Some(slot) => {
+                        *slot = b;
+                        self.len += 1;
+                    }
+                    None => self.overflow = true,
+                }
+                continue;
+            }
+
+            if self.overflow {
+                on_frame(Err(Error::TooLong));
+            } else if self.len > 0 {
+                on_frame(decode(&mut self.buf[..self.len]));
+            }
+
+            self.len = 0;
+            self.overflow = false;
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{format, string::String, vec::Vec};
+
+    fn log(message: &str) -> Frame<'_> {
+        Frame::Log(LogRecord {
+            uptime_ms: 1234,
+            level: Level::Info,
+            module: "rp_pico2w_examples::network",
+            task: Some("net"),
+            message,
+        })
+    }
+
+    fn encode_vec(frame: &Frame<'_>) -> Vec<u8> {
+        let mut buf = [0; MAX_FRAME_LEN];
+        encode(frame, &mut buf).unwrap().to_vec()
+    }
+
+    // the frames are compared by their debug output because they borrow the decoder's buffer
+    fn feed(decoder: &mut Decoder, data: &[u8]) -> Vec<Result<String, Error>> {
+        let mut frames = Vec::new();
+        decoder.feed(data, |frame| {
+            frames.push(frame.map(|frame| format!("{frame:?}")))
+        });
+        frames
+    }
+
+    fn decoded(frame: &Frame<'_>) -> Result<String, Error> {
+        Ok(format!("{frame:?}"))
+    }
+
+    #[test]
+    fn round_trip() {
+        for frame in [
+            log("connected to wifi network"),
+            log(""),
+            Frame::Shell("ok"),
+        ] {
+            let mut encoded = encode_vec(&frame);
+            let (end, body) = encoded.split_last_mut().unwrap();
+            assert_eq!(*end, 0);
+            assert!(!body.contains(&0));
+            assert_eq!(decode(body), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn round_trip_with_zero_bytes() {
+        // postcard writes zeros for the level and short lengths, COBS has to remove them all
+        let frame = Frame::Log(LogRecord {
+            uptime_ms: 0,
+            level: Level::Error,
+            module: "",
+            task: None,
+            message: "\0",
+        });
+        let mut encoded = encode_vec(&frame);
+        let len = encoded.len() - 1;
+        assert!(!encoded[..len].contains(&0));
+        assert_eq!(decode(&mut encoded[..len]), Ok(frame));
+    }
+
+    #[test]
+    fn rejects_corrupted_checksum() {
+        let encoded = encode_vec(&log("hello"));
+        let mut raw = [0; MAX_FRAME_LEN];
+        let len = cobs::decode(&encoded[..encoded.len() - 1], &mut raw).unwrap();
+        raw[len - 1] ^= 0x01;
+        let mut corrupted = [0; MAX_FRAME_LEN];
+        let len = cobs::try_encode(&raw[..len], &mut corrupted).unwrap();
+
+        assert_eq!(decode(&mut corrupted[..len]), Err(Error::Checksum));
+    }
+
+    #[test]
+    fn rejects_corrupted_payload() {
+        let mut encoded = encode_vec(&log("hello"));
+        let len = encoded.len() - 1;
+        // a byte of the message, not a COBS code byte
+        encoded[len - 6] ^= 0x20;
+        assert_eq!(decode(&mut encoded[..len]), Err(Error::Checksum));
+    }
+
+    #[test]
+    fn rejects_short_frames() {
+        assert_eq!(decode(&mut [0x01]), Err(Error::Checksum));
+        assert_eq!(decode(&mut [0x03, 0x01, 0x02]), Err(Error::Checksum));
+    }
+
+    #[test]
+    fn rejects_invalid_encoding() {
+        // the code byte promises more bytes than there are
+        assert_eq!(decode(&mut [0x09, 0x01, 0x02]), Err(Error::Encoding));
+    }
+
+    #[test]
+    fn rejects_oversized_frames() {
+        let message = "x".repeat(MAX_FRAME_LEN);
+        let mut buf = [0; MAX_FRAME_LEN];
+        assert_eq!(encode(&log(&message), &mut buf), Err(Error::TooLong));
+
+        // the largest message that fits
+        let message = "x".repeat(MAX_PAYLOAD_LEN - 40);
+        let frame = log(&message);
+        let encoded = encode(&frame, &mut buf).unwrap();
+        assert!(encoded.len() <= MAX_FRAME_LEN);
+
+        // a buffer too small for the frame
+        assert_eq!(encode(&frame, &mut [0; 64]), Err(Error::TooLong));
+    }
+
+    #[test]
+    fn skips_partial_frame_at_stream_start() {
+        let first = encode_vec(&log("sent before the host started reading"));
+        let second = encode_vec(&log("first complete frame"));
+        let mut stream = first[first.len() / 2..].to_vec();
+        stream.extend_from_slice(&second);
+
+        let mut decoder = Decoder::new();
+        let frames = feed(&mut decoder, &stream);
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_err());
+        assert_eq!(frames[1], decoded(&log("first complete frame")));
+    }
+
+    #[test]
+    fn recovers_after_oversized_frame() {
+        let mut stream = std::vec![0x55; MAX_FRAME_LEN + 10];
+        stream.push(0);
+        stream.extend_from_slice(&encode_vec(&Frame::Shell("ok")));
+
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            feed(&mut decoder, &stream),
+            [Err(Error::TooLong), decoded(&Frame::Shell("ok"))]
+        );
+    }
+
+    #[test]
+    fn ignores_empty_frames() {
+        let mut decoder = Decoder::new();
+        assert_eq!(feed(&mut decoder, &[0, 0, 0]), []);
+    }
+
+    #[test]
+    fn joins_frames_split_across_reads() {
+        let frames = [log("one"), Frame::Shell("two"), log("three")];
+        let stream: Vec<u8> = frames.iter().flat_map(encode_vec).collect();
+
+        for chunk_len in [1, 3, 7, stream.len()] {
+            let mut decoder = Decoder::new();
+            let decoded_frames: Vec<_> = stream
+                .chunks(chunk_len)
+                .flat_map(|chunk| feed(&mut decoder, chunk))
+                .collect();
+            let expected: Vec<_> = frames.iter().map(decoded).collect();
+            assert_eq!(decoded_frames, expected, "chunks of {chunk_len} bytes");
+        }
+    }
+}
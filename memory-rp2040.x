@@ -0,0 +1,100 @@
+MEMORY {
+    /*
+     * The second stage bootloader from embassy-rp goes in the first 256 bytes of flash.
+     *
+     * The Pico W has 2 MiB of flash, the last 512K hold the radio firmware.
+     */
+    BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
+    FLASH : ORIGIN = 0x10000100, LENGTH = 1536K - 0x100
+    FLASH_EXTRA : ORIGIN = 0x10180000, LENGTH = 512K - 4K
+    /*
+     * The last flash sector holds the Wi-Fi credentials saved by BLE provisioning.
+     */
+    WIFI_CREDENTIALS : ORIGIN = 0x101FF000, LENGTH = 4K
+    /*
+     * RAM consists of 4 banks, SRAM0-SRAM3, with a striped mapping.
+     */
+    RAM : ORIGIN = 0x20000000, LENGTH = 256K
+    /*
+     * RAM banks 4 and 5 use a direct mapping.
+     */
+    SRAM4 : ORIGIN = 0x20040000, LENGTH = 4K
+    SRAM5 : ORIGIN = 0x20041000, LENGTH = 4K
+}
+
+SECTIONS {
+    /* ### Boot loader
+     *
+     * The boot rom copies this to RAM and runs it before anything else
+     */
+    .boot2 ORIGIN(BOOT2) :
+    {
+        KEEP(*(.boot2));
+    } > BOOT2
+} INSERT BEFORE .text;
+
+SECTIONS {
+    /* ### Boot ROM info
+     *
+     * Goes after .vector_table, to keep it in the first 512 bytes of flash
+     * where picotool can find it
+     */
+    .boot_info : ALIGN(4)
+    {
+        KEEP(*(.boot_info));
+    } > FLASH
+
+} INSERT AFTER .vector_table;
+
+/* move .text to start /after/ the boot info */
+_stext = ADDR(.boot_info) + SIZEOF(.boot_info);
+
+SECTIONS {
+    /* ### Picotool 'Binary Info' Entries
+     *
+     * Picotool looks through this block (as we have pointers to it in our
+     * header) to find interesting information.
+     */
+    .bi_entries : ALIGN(4)
+    {
+        /* We put this in the header */
+        __bi_entries_start = .;
+        /* Here are the entries */
+        KEEP(*(.bi_entries));
+        /* Keep this block a nice round size */
+        . = ALIGN(4);
+        /* We put this in the header */
+        __bi_entries_end = .;
+    } > FLASH
+} INSERT AFTER .text;
+
+SECTIONS
+{
+  .modem_firmware : {
+    *(.modem_firmware .modem_firmware.*);
+    . = ALIGN(4);
+    } > FLASH_EXTRA
+}
+
+SECTIONS
+{
+  /* ### Wi-Fi credentials
+   *
+   * Not part of the firmware image so that flashing a new firmware keeps the saved credentials
+   */
+  .wifi_credentials (NOLOAD) : ALIGN(4096) {
+    KEEP(*(.wifi_credentials .wifi_credentials.*));
+    } > WIFI_CREDENTIALS
+}
+
+SECTIONS
+{
+  /* ### Crash report
+   *
+   * Not initialised at startup so that the report of a panic survives the reboot that follows it
+   */
+  .crash_report (NOLOAD) : ALIGN(4) {
+    KEEP(*(.crash_report .crash_report.*));
+    . = ALIGN(4);
+    } > SRAM4
+}
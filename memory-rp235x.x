@@ -5,7 +5,11 @@ MEMORY {
      * 2 MiB is a safe default here, although a Pico 2 has 4 MiB.
      */
     FLASH : ORIGIN = 0x10000000, LENGTH = 2048K
-    FLASH_EXTRA : ORIGIN = 0x10200000, LENGTH = 2048K 
+    FLASH_EXTRA : ORIGIN = 0x10200000, LENGTH = 2048K - 4K
+    /*
+     * The last flash sector holds the Wi-Fi credentials saved by BLE provisioning.
+     */
+    WIFI_CREDENTIALS : ORIGIN = 0x103FF000, LENGTH = 4K
     /*
      * RAM consists of 8 banks, SRAM0-SRAM7, with a striped mapping.
      * This is usually good for performance, as it distributes load on
@@ -79,5 +83,28 @@ SECTIONS
     } > FLASH_EXTRA
 }
 
+SECTIONS
+{
+  /* ### Wi-Fi credentials
+   *
+   * Not part of the firmware image so that flashing a new firmware keeps the saved credentials
+   */
+  .wifi_credentials (NOLOAD) : ALIGN(4096) {
+    KEEP(*(.wifi_credentials .wifi_credentials.*));
+    } > WIFI_CREDENTIALS
+}
+
 PROVIDE(start_to_end = __end_block_addr - __start_block_addr);
 PROVIDE(end_to_start = __start_block_addr - __end_block_addr);
+
+SECTIONS
+{
+  /* ### Crash report
+   *
+   * Not initialised at startup so that the report of a panic survives the reboot that follows it
+   */
+  .crash_report (NOLOAD) : ALIGN(4) {
+    KEEP(*(.crash_report .crash_report.*));
+    . = ALIGN(4);
+    } > SRAM4
+}
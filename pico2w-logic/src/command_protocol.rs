@@ -0,0 +1,244 @@
+use core::fmt::{self, Write};
+
+use heapless::String;
+
+// The line based protocol spoken on the data / command usb serial port. Every request carries an id chosen by the host
+// which is repeated at the start of every line of the response, so replies can be matched to requests and never mixed
+// up with anything else. A request is a shell command line:
+// `7 status`
+// The response is zero or more output lines followed by exactly one `ok` or `err` line:
+// `7 - uptime: 1.234s`
+// `7 - log filter: info`
+// `7 ok`
+// A request without a valid id is answered with `? err <message>`. A response that does not fit into the buffer loses
+// its last output lines and ends with `<id> err response truncated` instead, so it is never mistaken for a complete one.
+
+pub const RESPONSE_TRUNCATED: &str = "response truncated";
+// the longest id followed by ` err response truncated\r\n`
+const TRUNCATED_END_LEN: usize = 10 + 5 + RESPONSE_TRUNCATED.len() + 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestError {
+    InvalidId,
+    // the id of the request is still known so the error can be sent with it
+    MissingCommand(u32),
+}
+
+impl RequestError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::InvalidId => "request must start with a numeric id",
+            Self::MissingCommand(_) => "missing command",
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub id: u32,
+    pub command: &'a str,
+}
+
+pub fn parse_request(line: &str) -> Result<Request<'_>, RequestError> {
+    let line = line.trim();
+    let (id, command) = line.split_once(' ').unwrap_or((line, ""));
+    let id = id.parse().map_err(|_| RequestError::InvalidId)?;
+
+    let command = command.trim();
+    if command.is_empty() {
+        return Err(RequestError::MissingCommand(id));
+    }
+
+    Ok(Request { id, command })
+}
+
+// Writes the response to request `id` (`None` if the request had no valid id). Every line ends with `\r\n`,
+// `output` is split into lines and empty lines are left out. `N` must leave room for at least the `err` line of a
+// truncated response.
+pub fn write_response<const N: usize>(
+    response: &mut String<N>,
+    id: Option<u32>,
+    output: &str,
+    result: Result<(), &str>,
+) {
+    let mut result = result;
+    for line in output
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+    {
+        let len = response.len();
+        if write_output_line(response, id, line).is_err() || N - response.len() < TRUNCATED_END_LEN
+        {
+            response.truncate(len);
+            result = Err(RESPONSE_TRUNCATED);
+            break;
+        }
+    }
+
+    let len = response.len();
+    if write_result_line(response, id, result).is_err() {
+        // the error message is too long
+        response.truncate(len);
+        let _ = write_result_line(response, id, Err(RESPONSE_TRUNCATED));
+    }
+}
+
+fn write_output_line(out: &mut impl Write, id: Option<u32>, line: &str) -> fmt::Result {
+    write_id(out, id)?;
+    write!(out, " - {line}\r\n")
+}
+
+fn write_result_line(
+    out: &mut impl Write,
+    id: Option<u32>,
+    result: Result<(), &str>,
+) -> fmt::Result {
+    write_id(out, id)?;
+    match result {
+        Ok(()) => out.write_str(" ok\r\n"),
+        // the message must stay on one line
+        Err(message) => {
+            out.write_str(" err ")?;
+            for c in message.chars() {
+                out.write_char(if c.is_control() { ' ' } else { c })?;
+            }
+            out.write_str("\r\n")
+        }
+    }
+}
+
+fn write_id(out: &mut impl Write, id: Option<u32>) -> fmt::Result {
+    match id {
+        Some(id) => write!(out, "{id}"),
+        None => out.write_char('?'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response<const N: usize>(
+        id: Option<u32>,
+        output: &str,
+        result: Result<(), &str>,
+    ) -> String<N> {
+        let mut response = String::new();
+        write_response(&mut response, id, output, result);
+        response
+    }
+
+    #[test]
+    fn parses_requests() {
+        assert_eq!(
+            parse_request("7 status"),
+            Ok(Request {
+                id: 7,
+                command: "status"
+            })
+        );
+        assert_eq!(
+            parse_request("  42   log-level debug \r"),
+            Ok(Request {
+                id: 42,
+                command: "log-level debug"
+            })
+        );
+        assert_eq!(parse_request("4294967295 x").unwrap().id, u32::MAX);
+    }
+
+    #[test]
+    fn passes_unknown_commands_on() {
+        // the shell answers them with an error, see `write_response`
+        assert_eq!(
+            parse_request("3 no-such-command --flag"),
+            Ok(Request {
+                id: 3,
+                command: "no-such-command --flag"
+            })
+        );
+        assert_eq!(
+            response::<64>(Some(3), "", Err("unknown command 'no-such-command'")),
+            "3 err unknown command 'no-such-command'\r\n"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_requests() {
+        assert_eq!(parse_request(""), Err(RequestError::InvalidId));
+        assert_eq!(parse_request("   "), Err(RequestError::InvalidId));
+        assert_eq!(parse_request("7"), Err(RequestError::MissingCommand(7)));
+        assert_eq!(parse_request("7    "), Err(RequestError::MissingCommand(7)));
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        assert_eq!(parse_request("status"), Err(RequestError::InvalidId));
+        assert_eq!(parse_request("-1 status"), Err(RequestError::InvalidId));
+        assert_eq!(parse_request("1.5 status"), Err(RequestError::InvalidId));
+        // larger than a u32
+        assert_eq!(
+            parse_request("4294967296 status"),
+            Err(RequestError::InvalidId)
+        );
+        assert_eq!(
+            parse_request(&"9".repeat(200)),
+            Err(RequestError::InvalidId)
+        );
+    }
+
+    #[test]
+    fn writes_responses() {
+        assert_eq!(
+            response::<128>(Some(7), "uptime: 1.234s\r\n\nlog filter: info", Ok(())),
+            "7 - uptime: 1.234s\r\n7 - log filter: info\r\n7 ok\r\n"
+        );
+        assert_eq!(
+            response::<128>(None, "", Err("request must\nstart with an id")),
+            "? err request must start with an id\r\n"
+        );
+    }
+
+    #[test]
+    fn truncates_long_output() {
+        let output = "a line of output\n".repeat(20);
+        let response = response::<128>(Some(7), &output, Ok(()));
+        assert!(response.ends_with("7 err response truncated\r\n"));
+        assert!(response.starts_with("7 - a line of output\r\n"));
+        // only whole lines are kept
+        assert!(response
+            .lines()
+            .all(|line| line == "7 - a line of output" || line == "7 err response truncated"));
+        assert_eq!(response.matches(" err ").count(), 1);
+    }
+
+    #[test]
+    fn truncates_long_error_messages() {
+        let message = "x".repeat(100);
+        assert_eq!(
+            response::<64>(Some(7), "", Err(&message)),
+            "7 err response truncated\r\n"
+        );
+    }
+
+    #[test]
+    fn keeps_output_that_just_fits() {
+        // the output line plus the space kept for a truncated end
+        const N: usize = "1 - abcde\r\n".len() + TRUNCATED_END_LEN;
+        assert_eq!(
+            response::<N>(Some(1), "abcde", Ok(())),
+            "1 - abcde\r\n1 ok\r\n"
+        );
+        assert_eq!(
+            response::<N>(Some(1), "abcdef", Ok(())),
+            "1 err response truncated\r\n"
+        );
+    }
+}
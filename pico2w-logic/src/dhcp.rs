@@ -0,0 +1,459 @@
+use core::{fmt, net::Ipv4Addr};
+
+use heapless::Vec;
+
+// DHCPv4 messages (RFC 2131 with the options of RFC 2132) as far as the server of the access point mode needs them,
+// see `dhcp_server.rs` in the firmware: parsing the requests of clients and writing the replies. Everything after the fixed 236 byte
+// header and the magic cookie is a list of options, each a code, a length and that many bytes of data.
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY: u8 = 2;
+const HTYPE_ETHERNET: u8 = 1;
+const HLEN_ETHERNET: u8 = 6;
+const FLAG_BROADCAST: u16 = 0x8000;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const HEADER_LEN: usize = 236;
+const OPTIONS_OFFSET: usize = HEADER_LEN + MAGIC_COOKIE.len();
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_HOSTNAME: u8 = 12;
+const OPTION_REQUESTED_IP: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+pub const MAX_DNS_SERVERS: usize = 3;
+// some clients drop replies shorter than a BOOTP message, the options of a reply always fit
+pub const REPLY_LEN: usize = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Ack = 5,
+    Nak = 6,
+    Release = 7,
+    Inform = 8,
+}
+
+impl MessageType {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            8 => Self::Inform,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    TooShort,
+    // a reply or a message for another kind of network
+    NotRequest,
+    MagicCookie,
+    Options,
+    MissingMessageType,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::TooShort => "message too short",
+            Self::NotRequest => "not a request from an ethernet client",
+            Self::MagicCookie => "invalid magic cookie",
+            Self::Options => "invalid options",
+            Self::MissingMessageType => "missing message type",
+        })
+    }
+}
+
+// a message from a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request<'a> {
+    pub message_type: MessageType,
+    pub xid: u32,
+    pub flags: u16,
+    // the address of a client that renews its lease
+    pub ciaddr: Ipv4Addr,
+    pub giaddr: Ipv4Addr,
+    pub mac: [u8; 6],
+    pub requested_ip: Option<Ipv4Addr>,
+    // the server the client picked from the offers it got
+    pub server_id: Option<Ipv4Addr>,
+    pub hostname: Option<&'a str>,
+}
+
+pub fn parse_request(packet: &[u8]) -> Result<Request<'_>, ParseError> {
+    if packet.len() < OPTIONS_OFFSET {
+        return Err(ParseError::TooShort);
+    }
+    if packet[0] != OP_REQUEST || packet[1] != HTYPE_ETHERNET || packet[2] != HLEN_ETHERNET {
+        return Err(ParseError::NotRequest);
+    }
+    if packet[HEADER_LEN..OPTIONS_OFFSET] != MAGIC_COOKIE {
+        return Err(ParseError::MagicCookie);
+    }
+
+    let mut message_type = None;
+    let mut requested_ip = None;
+    let mut server_id = None;
+    let mut hostname = None;
+
+    let mut options = &packet[OPTIONS_OFFSET..];
+    while let Some((&code, rest)) = options.split_first() {
+        match code {
+            OPTION_PAD => {
+                options = rest;
+                continue;
+            }
+            OPTION_END => break,
+            _ => {}
+        }
+
+        let (&len, rest) = rest.split_first().ok_or(ParseError::Options)?;
+        if rest.len() < len as usize {
+            return Err(ParseError::Options);
+        }
+        let (data, rest) = rest.split_at(len as usize);
+        options = rest;
+
+        match code {
+            OPTION_MESSAGE_TYPE => {
+                message_type = data.first().copied().and_then(MessageType::from_u8);
+            }
+            OPTION_REQUESTED_IP => requested_ip = ipv4(data),
+            OPTION_SERVER_ID => server_id = ipv4(data),
+            OPTION_HOSTNAME => hostname = core::str::from_utf8(data).ok(),
+            _ => {}
+        }
+    }
+
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&packet[28..34]);
+
+    Ok(Request {
+        message_type: message_type.ok_or(ParseError::MissingMessageType)?,
+        xid: u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]),
+        flags: u16::from_be_bytes([packet[10], packet[11]]),
+        ciaddr: ipv4_at(packet, 12),
+        giaddr: ipv4_at(packet, 24),
+        mac,
+        requested_ip,
+        server_id,
+        hostname,
+    })
+}
+
+// what the server tells its clients about the network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerOptions {
+    pub server: Ipv4Addr,
+    pub subnet_mask: Ipv4Addr,
+    pub router: Option<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr, MAX_DNS_SERVERS>,
+    pub lease_time_secs: u32,
+}
+
+// Writes the `message_type` reply to `request` that gives the client `your_ip` (unspecified for a NAK)
+pub fn write_reply(
+    request: &Request<'_>,
+    message_type: MessageType,
+    your_ip: Ipv4Addr,
+    options: &ServerOptions,
+) -> [u8; REPLY_LEN] {
+    let mut reply = [0; REPLY_LEN];
+    reply[0] = OP_REPLY;
+    reply[1] = HTYPE_ETHERNET;
+    reply[2] = HLEN_ETHERNET;
+    reply[4..8].copy_from_slice(&request.xid.to_be_bytes());
+    reply[10..12].copy_from_slice(&request.flags.to_be_bytes());
+    reply[16..20].copy_from_slice(&your_ip.octets());
+    reply[24..28].copy_from_slice(&request.giaddr.octets());
+    reply[28..34].copy_from_slice(&request.mac);
+    reply[HEADER_LEN..OPTIONS_OFFSET].copy_from_slice(&MAGIC_COOKIE);
+
+    let mut writer = OptionWriter {
+        buf: &mut reply,
+        len: OPTIONS_OFFSET,
+    };
+    writer.write(OPTION_MESSAGE_TYPE, &[message_type as u8]);
+    writer.write(OPTION_SERVER_ID, &options.server.octets());
+    if message_type != MessageType::Nak {
+        writer.write(OPTION_LEASE_TIME, &options.lease_time_secs.to_be_bytes());
+        writer.write(OPTION_SUBNET_MASK, &options.subnet_mask.octets());
+        if let Some(router) = options.router {
+            writer.write(OPTION_ROUTER, &router.octets());
+        }
+        if !options.dns_servers.is_empty() {
+            let mut dns = [0; 4 * MAX_DNS_SERVERS];
+            for (chunk, server) in dns.chunks_mut(4).zip(&options.dns_servers) {
+                chunk.copy_from_slice(&server.octets());
+            }
+            writer.write(OPTION_DNS, &dns[..4 * options.dns_servers.len()]);
+        }
+    }
+    let end = writer.len;
+    reply[end] = OPTION_END;
+
+    reply
+}
+
+// A client without an address cannot receive a unicast reply before it knows its address, so everything but the reply
+// to a client renewing its lease is broadcast
+pub fn reply_destination(request: &Request<'_>, message_type: MessageType) -> Ipv4Addr {
+    if message_type != MessageType::Nak
+        && !request.ciaddr.is_unspecified()
+        && request.flags & FLAG_BROADCAST == 0
+    {
+        request.ciaddr
+    } else {
+        Ipv4Addr::BROADCAST
+    }
+}
+
+struct OptionWriter<'a> {
+    buf: &'a mut [u8; REPLY_LEN],
+    len: usize,
+}
+
+impl OptionWriter<'_> {
+    fn write(&mut self, code: u8, data: &[u8]) {
+        self.buf[self.len] = code;
+        self.buf[self.len + 1] = data.len() as u8;
+        self.buf[self.len + 2..self.len + 2 + data.len()].copy_from_slice(data);
+        self.len += 2 + data.len();
+    }
+}
+
+fn ipv4_at(packet: &[u8], offset: usize) -> Ipv4Addr {
+    Ipv4Addr::new(
+        packet[offset],
+        packet[offset + 1],
+        packet[offset + 2],
+        packet[offset + 3],
+    )
+}
+
+fn ipv4(data: &[u8]) -> Option<Ipv4Addr> {
+    let octets: [u8; 4] = data.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [2, 0, 0, 0, 0, 1];
+    const XID: u32 = 0xdead_beef;
+
+    // a broadcast request from `MAC` followed by `options`
+    fn request(options: &[u8]) -> std::vec::Vec<u8> {
+        let mut packet = std::vec![0; OPTIONS_OFFSET];
+        packet[0] = OP_REQUEST;
+        packet[1] = HTYPE_ETHERNET;
+        packet[2] = HLEN_ETHERNET;
+        packet[4..8].copy_from_slice(&XID.to_be_bytes());
+        packet[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        packet[28..34].copy_from_slice(&MAC);
+        packet[HEADER_LEN..OPTIONS_OFFSET].copy_from_slice(&MAGIC_COOKIE);
+        packet.extend_from_slice(options);
+        packet
+    }
+
+    fn server_options() -> ServerOptions {
+        ServerOptions {
+            server: Ipv4Addr::new(192, 168, 4, 1),
+            subnet_mask: Ipv4Addr::new(255, 255, 255, 0),
+            router: Some(Ipv4Addr::new(192, 168, 4, 1)),
+            dns_servers: Vec::from_slice(&[Ipv4Addr::new(1, 1, 1, 1), Ipv4Addr::new(8, 8, 8, 8)])
+                .unwrap(),
+            lease_time_secs: 3600,
+        }
+    }
+
+    #[test]
+    fn parses_discover() {
+        let packet = request(&[
+            53, 1, 1, // discover
+            0, // pad
+            12, 3, b'a', b'b', b'c', // hostname
+            50, 4, 192, 168, 4, 9, // requested ip
+            255,
+        ]);
+        let request = parse_request(&packet).unwrap();
+
+        assert_eq!(request.message_type, MessageType::Discover);
+        assert_eq!(request.xid, XID);
+        assert_eq!(request.flags, FLAG_BROADCAST);
+        assert_eq!(request.mac, MAC);
+        assert_eq!(request.ciaddr, Ipv4Addr::UNSPECIFIED);
+        assert_eq!(request.hostname, Some("abc"));
+        assert_eq!(request.requested_ip, Some(Ipv4Addr::new(192, 168, 4, 9)));
+        assert_eq!(request.server_id, None);
+    }
+
+    #[test]
+    fn parses_options_without_end() {
+        let packet = request(&[53, 1, 3, 54, 4, 192, 168, 4, 1]);
+        let request = parse_request(&packet).unwrap();
+        assert_eq!(request.message_type, MessageType::Request);
+        assert_eq!(request.server_id, Some(Ipv4Addr::new(192, 168, 4, 1)));
+    }
+
+    #[test]
+    fn ignores_options_after_end() {
+        let packet = request(&[53, 1, 1, 255, 53, 1, 7]);
+        let request = parse_request(&packet).unwrap();
+        assert_eq!(request.message_type, MessageType::Discover);
+    }
+
+    #[test]
+    fn ignores_malformed_addresses() {
+        let packet = request(&[53, 1, 1, 50, 3, 192, 168, 4, 255]);
+        let request = parse_request(&packet).unwrap();
+        assert_eq!(request.requested_ip, None);
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        let packet = request(&[53, 1, 1, 255]);
+        assert_eq!(parse_request(&packet[..100]), Err(ParseError::TooShort));
+        assert_eq!(
+            parse_request(&packet[..OPTIONS_OFFSET - 1]),
+            Err(ParseError::TooShort)
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_options() {
+        // the length runs past the end of the message
+        assert_eq!(
+            parse_request(&request(&[53, 1, 1, 50, 4, 192, 168])),
+            Err(ParseError::Options)
+        );
+        // the length itself is missing
+        assert_eq!(
+            parse_request(&request(&[53, 1, 1, 12])),
+            Err(ParseError::Options)
+        );
+    }
+
+    #[test]
+    fn rejects_missing_message_type() {
+        assert_eq!(
+            parse_request(&request(&[255])),
+            Err(ParseError::MissingMessageType)
+        );
+        assert_eq!(
+            parse_request(&request(&[])),
+            Err(ParseError::MissingMessageType)
+        );
+        // an unknown message type
+        assert_eq!(
+            parse_request(&request(&[53, 1, 42, 255])),
+            Err(ParseError::MissingMessageType)
+        );
+    }
+
+    #[test]
+    fn rejects_other_messages() {
+        let mut packet = request(&[53, 1, 1, 255]);
+        packet[0] = OP_REPLY;
+        assert_eq!(parse_request(&packet), Err(ParseError::NotRequest));
+
+        let mut packet = request(&[53, 1, 1, 255]);
+        packet[HEADER_LEN] = 0;
+        assert_eq!(parse_request(&packet), Err(ParseError::MagicCookie));
+    }
+
+    #[test]
+    fn writes_offer() {
+        let packet = request(&[53, 1, 1, 255]);
+        let request = parse_request(&packet).unwrap();
+        let reply = write_reply(
+            &request,
+            MessageType::Offer,
+            Ipv4Addr::new(192, 168, 4, 2),
+            &server_options(),
+        );
+
+        assert_eq!(reply[0], OP_REPLY);
+        assert_eq!(reply[4..8], XID.to_be_bytes());
+        assert_eq!(reply[10..12], FLAG_BROADCAST.to_be_bytes());
+        assert_eq!(reply[16..20], [192, 168, 4, 2]);
+        assert_eq!(reply[28..34], MAC);
+        assert_eq!(reply[HEADER_LEN..OPTIONS_OFFSET], MAGIC_COOKIE);
+
+        #[rustfmt::skip]
+        let options = [
+            53, 1, 2,
+            54, 4, 192, 168, 4, 1,
+            51, 4, 0, 0, 0x0e, 0x10,
+            1, 4, 255, 255, 255, 0,
+            3, 4, 192, 168, 4, 1,
+            6, 8, 1, 1, 1, 1, 8, 8, 8, 8,
+            255,
+        ];
+        let end = OPTIONS_OFFSET + options.len();
+        assert_eq!(reply[OPTIONS_OFFSET..end], options);
+        assert!(reply[end..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn writes_nak_without_lease_options() {
+        let packet = request(&[53, 1, 3, 255]);
+        let request = parse_request(&packet).unwrap();
+        let reply = write_reply(
+            &request,
+            MessageType::Nak,
+            Ipv4Addr::UNSPECIFIED,
+            &server_options(),
+        );
+
+        assert_eq!(reply[16..20], [0; 4]);
+        assert_eq!(
+            reply[OPTIONS_OFFSET..OPTIONS_OFFSET + 10],
+            [53, 1, 6, 54, 4, 192, 168, 4, 1, 255]
+        );
+    }
+
+    #[test]
+    fn unicasts_only_to_renewing_clients() {
+        let packet = request(&[53, 1, 1, 255]);
+        let discover = parse_request(&packet).unwrap();
+        assert_eq!(
+            reply_destination(&discover, MessageType::Offer),
+            Ipv4Addr::BROADCAST
+        );
+
+        let mut packet = request(&[53, 1, 3, 255]);
+        packet[10..12].copy_from_slice(&[0, 0]);
+        packet[12..16].copy_from_slice(&[192, 168, 4, 2]);
+        let renew = parse_request(&packet).unwrap();
+        assert_eq!(
+            reply_destination(&renew, MessageType::Ack),
+            Ipv4Addr::new(192, 168, 4, 2)
+        );
+        assert_eq!(
+            reply_destination(&renew, MessageType::Nak),
+            Ipv4Addr::BROADCAST
+        );
+    }
+}
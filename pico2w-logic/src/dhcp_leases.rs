@@ -0,0 +1,740 @@
+use core::net::Ipv4Addr;
+
+use crate::dhcp::{MessageType, Request};
+
+// The addresses handed out by the DHCP server (see `dhcp_server.rs` in the firmware) and the decisions on the requests of clients. Times
+// are seconds since boot, passed in by the caller so this runs the same on a PC.
+// A client first gets an offer which reserves an address for `OFFER_TIME_SECS`, requesting it makes that a lease for
+// the lease time. Expired leases are only given to another client once the pool has no unused address left, so a
+// client coming back usually gets its old address.
+
+pub const MAX_LEASES: usize = 32;
+pub const OFFER_TIME_SECS: u64 = 60;
+
+// `size` addresses starting at `start`, at most `MAX_LEASES`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pool {
+    pub start: Ipv4Addr,
+    pub size: usize,
+}
+
+impl Pool {
+    // Up to `size` addresses of the subnet of `server` next to it, after it unless there is more room before it. Never
+    // the server, the network or the broadcast address, so a small subnet gives a smaller pool.
+    pub fn in_subnet(server: Ipv4Addr, subnet_mask: Ipv4Addr, size: usize) -> Self {
+        let server = u32::from(server);
+        let mask = u32::from(subnet_mask);
+        let network = server & mask;
+        let broadcast = network | !mask;
+        let after = broadcast.saturating_sub(server).saturating_sub(1) as usize;
+        let before = server.saturating_sub(network).saturating_sub(1) as usize;
+
+        if after >= size.min(before) {
+            let size = size.min(after);
+            // `after` is 0 for the last address so this only falls back for an empty pool
+            let start = server.checked_add(1).unwrap_or(server);
+            Self {
+                start: Ipv4Addr::from(start),
+                size,
+            }
+        } else {
+            let size = size.min(before);
+            Self {
+                start: Ipv4Addr::from(server - size as u32),
+                size,
+            }
+        }
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        self.index(address).is_some()
+    }
+
+    fn index(&self, address: Ipv4Addr) -> Option<usize> {
+        let index = u32::from(address).checked_sub(u32::from(self.start))? as usize;
+        (index < self.size).then_some(index)
+    }
+
+    fn address(&self, index: usize) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.start) + index as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Holder {
+    Client([u8; 6]),
+    // a client found the address in use on the network (DHCPDECLINE), it is not handed out until the lease time passed
+    Declined,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lease {
+    pub holder: Holder,
+    pub expires_secs: u64,
+    // only offered, the client has not requested it yet
+    pub offered: bool,
+}
+
+impl Lease {
+    fn is_expired(&self, now_secs: u64) -> bool {
+        now_secs >= self.expires_secs
+    }
+
+    fn is_held_by(&self, mac: [u8; 6]) -> bool {
+        self.holder == Holder::Client(mac)
+    }
+}
+
+pub struct LeaseTable {
+    pool: Pool,
+    lease_time_secs: u32,
+    leases: [Option<Lease>; MAX_LEASES],
+}
+
+impl LeaseTable {
+    pub fn new(pool: Pool, lease_time_secs: u32) -> Self {
+        assert!(pool.size <= MAX_LEASES, "dhcp pool larger than MAX_LEASES");
+        Self {
+            pool,
+            lease_time_secs,
+            leases: [None; MAX_LEASES],
+        }
+    }
+
+    // The reply to `request` and the address it gives the client, `None` if the request needs no reply. `server` is
+    // the address of this server, requests naming another server are for that one.
+    pub fn handle(
+        &mut self,
+        request: &Request<'_>,
+        server: Ipv4Addr,
+        now_secs: u64,
+    ) -> Option<(MessageType, Ipv4Addr)> {
+        match request.message_type {
+            MessageType::Discover => {
+                let address = self.offer(request.mac, request.requested_ip, now_secs)?;
+                Some((MessageType::Offer, address))
+            }
+            MessageType::Request => {
+                // the client took the offer of another server
+                if request.server_id.is_some_and(|id| id != server) {
+                    self.withdraw_offer(request.mac);
+                    return None;
+                }
+
+                // a new client names its address in an option, a client renewing its lease in the header
+                let address = request
+                    .requested_ip
+                    .or((!request.ciaddr.is_unspecified()).then_some(request.ciaddr))?;
+                if self.request(request.mac, address, now_secs) {
+                    Some((MessageType::Ack, address))
+                } else {
+                    Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+                }
+            }
+            MessageType::Decline => {
+                if let Some(address) = request.requested_ip {
+                    self.decline(request.mac, address, now_secs);
+                }
+                None
+            }
+            MessageType::Release => {
+                self.release(request.mac, request.ciaddr);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // Reserves an address for `mac`: the one it already has, otherwise `requested` if that is free, otherwise the first
+    // unused address and then the one that expired first. `None` if the pool is exhausted.
+    pub fn offer(
+        &mut self,
+        mac: [u8; 6],
+        requested: Option<Ipv4Addr>,
+        now_secs: u64,
+    ) -> Option<Ipv4Addr> {
+        let index = match self.index_of(mac) {
+            Some(index) => index,
+            None => requested
+                .and_then(|address| self.pool.index(address))
+                .filter(|index| self.is_free(*index, now_secs))
+                .or_else(|| self.free_index(now_secs))?,
+        };
+
+        let lease = &mut self.leases[index];
+        match lease {
+            // the offer does not shorten a lease the client still holds
+            Some(lease) if !lease.offered && !lease.is_expired(now_secs) => {}
+            _ => {
+                *lease = Some(Lease {
+                    holder: Holder::Client(mac),
+                    expires_secs: now_secs + OFFER_TIME_SECS,
+                    offered: true,
+                })
+            }
+        }
+        Some(self.pool.address(index))
+    }
+
+    // Leases `address` to `mac` for the lease time (again), `false` if it is not in the pool or held by another client
+    pub fn request(&mut self, mac: [u8; 6], address: Ipv4Addr, now_secs: u64) -> bool {
+        let Some(index) = self.pool.index(address) else {
+            return false;
+        };
+
+        let held = self.leases[index].is_some_and(|lease| lease.is_held_by(mac));
+        if !held && !self.is_free(index, now_secs) {
+            return false;
+        }
+
+        // a client only holds one address
+        if let Some(other) = self.index_of(mac).filter(|other| *other != index) {
+            self.leases[other] = None;
+        }
+        self.leases[index] = Some(Lease {
+            holder: Holder::Client(mac),
+            expires_secs: now_secs + self.lease_time_secs as u64,
+            offered: false,
+        });
+        true
+    }
+
+    pub fn release(&mut self, mac: [u8; 6], address: Ipv4Addr) {
+        if let Some(index) = self.pool.index(address) {
+            if self.leases[index].is_some_and(|lease| lease.is_held_by(mac)) {
+                self.leases[index] = None;
+            }
+        }
+    }
+
+    pub fn decline(&mut self, mac: [u8; 6], address: Ipv4Addr, now_secs: u64) {
+        if let Some(index) = self.pool.index(address) {
+            if self.leases[index].is_some_and(|lease| lease.is_held_by(mac)) {
+                self.leases[index] = Some(Lease {
+                    holder: Holder::Declined,
+                    expires_secs: now_secs + self.lease_time_secs as u64,
+                    offered: false,
+                });
+            }
+        }
+    }
+
+    // the addresses that are leased or reserved
+    pub fn leases(&self) -> impl Iterator<Item = (Ipv4Addr, &Lease)> {
+        self.leases[..self.pool.size]
+            .iter()
+            .enumerate()
+            .filter_map(|(index, lease)| Some((self.pool.address(index), lease.as_ref()?)))
+    }
+
+    fn withdraw_offer(&mut self, mac: [u8; 6]) {
+        if let Some(index) = self.index_of(mac) {
+            if self.leases[index].is_some_and(|lease| lease.offered) {
+                self.leases[index] = None;
+            }
+        }
+    }
+
+    fn index_of(&self, mac: [u8; 6]) -> Option<usize> {
+        self.leases[..self.pool.size]
+            .iter()
+            .position(|lease| lease.is_some_and(|lease| lease.is_held_by(mac)))
+    }
+
+    fn is_free(&self, index: usize, now_secs: u64) -> bool {
+        self.leases[index].is_none_or(|lease| lease.is_expired(now_secs))
+    }
+
+    fn free_index(&self, now_secs: u64) -> Option<usize> {
+        let leases = &self.leases[..self.pool.size];
+        leases.iter().position(Option::is_none).or_else(|| {
+            (0..self.pool.size)
+                .filter(|index| self.is_free(*index, now_secs))
+                .min_by_key(|index| leases[*index].map_or(0, |lease| lease.expires_secs))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+    const POOL: Pool = Pool {
+        start: Ipv4Addr::new(192, 168, 4, 10),
+        size: 3,
+    };
+    const LEASE_TIME_SECS: u32 = 100;
+
+    fn address(last: u8) -> Ipv4Addr {
+        Ipv4Addr::new(192, 168, 4, last)
+    }
+
+    fn mac(client: u8) -> [u8; 6] {
+        [2, 0, 0, 0, 0, client]
+    }
+
+    fn message(message_type: MessageType, client: u8) -> Request<'static> {
+        Request {
+            message_type,
+            xid: 1,
+            flags: 0,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
+            mac: mac(client),
+            requested_ip: None,
+            server_id: None,
+            hostname: None,
+        }
+    }
+
+    fn discover(client: u8) -> Request<'static> {
+        message(MessageType::Discover, client)
+    }
+
+    // the request of a client that picked the offer of `server`
+    fn select(client: u8, requested: Ipv4Addr, server: Ipv4Addr) -> Request<'static> {
+        Request {
+            requested_ip: Some(requested),
+            server_id: Some(server),
+            ..message(MessageType::Request, client)
+        }
+    }
+
+    fn renew(client: u8, ciaddr: Ipv4Addr) -> Request<'static> {
+        Request {
+            ciaddr,
+            ..message(MessageType::Request, client)
+        }
+    }
+
+    fn table() -> LeaseTable {
+        LeaseTable::new(POOL, LEASE_TIME_SECS)
+    }
+
+    fn lease(table: &LeaseTable, address: Ipv4Addr) -> Option<Lease> {
+        table
+            .leases()
+            .find(|(leased, _)| *leased == address)
+            .map(|(_, lease)| *lease)
+    }
+
+    #[test]
+    fn offers_first_free_address() {
+        let mut table = table();
+        assert_eq!(
+            table.handle(&discover(1), SERVER, 0),
+            Some((MessageType::Offer, address(10)))
+        );
+        assert_eq!(
+            table.handle(&discover(2), SERVER, 0),
+            Some((MessageType::Offer, address(11)))
+        );
+        // a repeated discover gets the same offer
+        assert_eq!(
+            table.handle(&discover(1), SERVER, 1),
+            Some((MessageType::Offer, address(10)))
+        );
+    }
+
+    #[test]
+    fn offers_requested_address_if_free() {
+        let mut table = table();
+        let request = Request {
+            requested_ip: Some(address(12)),
+            ..discover(1)
+        };
+        assert_eq!(
+            table.handle(&request, SERVER, 0),
+            Some((MessageType::Offer, address(12)))
+        );
+
+        // taken, so the next client gets the first free one
+        let request = Request {
+            requested_ip: Some(address(12)),
+            ..discover(2)
+        };
+        assert_eq!(
+            table.handle(&request, SERVER, 0),
+            Some((MessageType::Offer, address(10)))
+        );
+    }
+
+    #[test]
+    fn acks_offered_address() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        assert_eq!(
+            table.handle(&select(1, address(10), SERVER), SERVER, 5),
+            Some((MessageType::Ack, address(10)))
+        );
+
+        let lease = lease(&table, address(10)).unwrap();
+        assert_eq!(lease.holder, Holder::Client(mac(1)));
+        assert_eq!(lease.expires_secs, 5 + LEASE_TIME_SECS as u64);
+        assert!(!lease.offered);
+    }
+
+    #[test]
+    fn renews_lease() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        table.handle(&select(1, address(10), SERVER), SERVER, 0);
+
+        assert_eq!(
+            table.handle(&renew(1, address(10)), SERVER, 50),
+            Some((MessageType::Ack, address(10)))
+        );
+        assert_eq!(
+            lease(&table, address(10)).unwrap().expires_secs,
+            50 + LEASE_TIME_SECS as u64
+        );
+
+        // a discover while holding the lease does not shorten it
+        assert_eq!(
+            table.handle(&discover(1), SERVER, 60),
+            Some((MessageType::Offer, address(10)))
+        );
+        assert_eq!(
+            lease(&table, address(10)).unwrap().expires_secs,
+            50 + LEASE_TIME_SECS as u64
+        );
+    }
+
+    #[test]
+    fn acks_free_address_of_unknown_client() {
+        // e.g. a client that still has a lease from before the board rebooted
+        let mut table = table();
+        assert_eq!(
+            table.handle(&renew(1, address(11)), SERVER, 0),
+            Some((MessageType::Ack, address(11)))
+        );
+
+        // moving to another address gives up the old one
+        let request = Request {
+            requested_ip: Some(address(12)),
+            ..message(MessageType::Request, 1)
+        };
+        assert_eq!(
+            table.handle(&request, SERVER, 0),
+            Some((MessageType::Ack, address(12)))
+        );
+        assert_eq!(table.leases().count(), 1);
+    }
+
+    #[test]
+    fn naks_address_of_another_client() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        table.handle(&discover(2), SERVER, 0);
+
+        assert_eq!(
+            table.handle(&select(2, address(10), SERVER), SERVER, 1),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+        assert_eq!(
+            lease(&table, address(10)).unwrap().holder,
+            Holder::Client(mac(1))
+        );
+    }
+
+    #[test]
+    fn naks_address_outside_pool() {
+        let mut table = table();
+        assert_eq!(
+            table.handle(&renew(1, Ipv4Addr::new(10, 0, 0, 5)), SERVER, 0),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+        assert_eq!(
+            table.handle(&renew(1, address(13)), SERVER, 0),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+        assert_eq!(
+            table.handle(&renew(1, address(9)), SERVER, 0),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+        assert_eq!(table.leases().count(), 0);
+    }
+
+    #[test]
+    fn ignores_request_for_another_server() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        assert_eq!(
+            table.handle(&select(1, address(10), address(99)), SERVER, 1),
+            None
+        );
+
+        // the withdrawn offer is free for the next client
+        assert_eq!(
+            table.handle(&discover(2), SERVER, 2),
+            Some((MessageType::Offer, address(10)))
+        );
+    }
+
+    #[test]
+    fn ignores_request_without_address() {
+        let mut table = table();
+        assert_eq!(
+            table.handle(&message(MessageType::Request, 1), SERVER, 0),
+            None
+        );
+        assert_eq!(
+            table.handle(&message(MessageType::Inform, 1), SERVER, 0),
+            None
+        );
+    }
+
+    #[test]
+    fn declined_address_is_not_handed_out() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        let decline = Request {
+            requested_ip: Some(address(10)),
+            server_id: Some(SERVER),
+            ..message(MessageType::Decline, 1)
+        };
+        assert_eq!(table.handle(&decline, SERVER, 1), None);
+        assert_eq!(lease(&table, address(10)).unwrap().holder, Holder::Declined);
+
+        assert_eq!(
+            table.handle(&discover(1), SERVER, 2),
+            Some((MessageType::Offer, address(11)))
+        );
+        assert_eq!(
+            table.handle(&renew(2, address(10)), SERVER, 2),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+
+        // until the lease time passed
+        assert_eq!(
+            table.handle(&renew(2, address(10)), SERVER, 1 + LEASE_TIME_SECS as u64),
+            Some((MessageType::Ack, address(10)))
+        );
+    }
+
+    #[test]
+    fn decline_of_another_client_is_ignored() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        let decline = Request {
+            requested_ip: Some(address(10)),
+            ..message(MessageType::Decline, 2)
+        };
+        table.handle(&decline, SERVER, 1);
+        assert_eq!(
+            lease(&table, address(10)).unwrap().holder,
+            Holder::Client(mac(1))
+        );
+    }
+
+    #[test]
+    fn release_frees_address() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        table.handle(&select(1, address(10), SERVER), SERVER, 0);
+
+        // only the client holding it can release it
+        let release = |client| Request {
+            ciaddr: address(10),
+            ..message(MessageType::Release, client)
+        };
+        assert_eq!(table.handle(&release(2), SERVER, 1), None);
+        assert!(lease(&table, address(10)).is_some());
+
+        assert_eq!(table.handle(&release(1), SERVER, 1), None);
+        assert!(lease(&table, address(10)).is_none());
+        assert_eq!(
+            table.handle(&discover(2), SERVER, 2),
+            Some((MessageType::Offer, address(10)))
+        );
+    }
+
+    #[test]
+    fn exhausted_pool_gets_no_offer() {
+        let mut table = table();
+        for client in 1..=3 {
+            assert!(table.handle(&discover(client), SERVER, 0).is_some());
+        }
+        assert_eq!(table.handle(&discover(4), SERVER, 0), None);
+        assert_eq!(
+            table.handle(&renew(4, address(10)), SERVER, 0),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+    }
+
+    #[test]
+    fn unrequested_offer_expires() {
+        let mut table = table();
+        for client in 1..=3 {
+            table.handle(&discover(client), SERVER, 0);
+        }
+        assert_eq!(
+            table.handle(&discover(4), SERVER, OFFER_TIME_SECS - 1),
+            None
+        );
+        assert_eq!(
+            table.handle(&discover(4), SERVER, OFFER_TIME_SECS),
+            Some((MessageType::Offer, address(10)))
+        );
+    }
+
+    #[test]
+    fn reuses_expired_lease() {
+        let mut table = table();
+        for client in 1..=3 {
+            table.handle(&discover(client), SERVER, 0);
+            let offered = POOL.address(client as usize - 1);
+            table.handle(&select(client, offered, SERVER), SERVER, client as u64);
+        }
+
+        // only the lease of client 1 has expired, it goes to the new client
+        let expired = 1 + LEASE_TIME_SECS as u64;
+        assert_eq!(
+            table.handle(&discover(4), SERVER, expired),
+            Some((MessageType::Offer, address(10)))
+        );
+        assert_eq!(
+            table.handle(&select(4, address(10), SERVER), SERVER, expired),
+            Some((MessageType::Ack, address(10)))
+        );
+
+        // and the client that lost it gets a NAK
+        assert_eq!(
+            table.handle(&renew(1, address(10)), SERVER, expired),
+            Some((MessageType::Nak, Ipv4Addr::UNSPECIFIED))
+        );
+    }
+
+    #[test]
+    fn prefers_unused_address_over_expired_lease() {
+        let mut table = table();
+        table.handle(&discover(1), SERVER, 0);
+        table.handle(&select(1, address(10), SERVER), SERVER, 0);
+
+        let expired = LEASE_TIME_SECS as u64 + 1;
+        assert_eq!(
+            table.handle(&discover(2), SERVER, expired),
+            Some((MessageType::Offer, address(11)))
+        );
+        // so the client coming back gets its old address
+        assert_eq!(
+            table.handle(&discover(1), SERVER, expired),
+            Some((MessageType::Offer, address(10)))
+        );
+    }
+
+    #[test]
+    fn pool_contains() {
+        assert!(!POOL.contains(address(9)));
+        assert!(POOL.contains(address(10)));
+        assert!(POOL.contains(address(12)));
+        assert!(!POOL.contains(address(13)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_pool_larger_than_table() {
+        LeaseTable::new(
+            Pool {
+                start: address(2),
+                size: MAX_LEASES + 1,
+            },
+            LEASE_TIME_SECS,
+        );
+    }
+
+    #[test]
+    fn pool_after_server() {
+        let mask = Ipv4Addr::new(255, 255, 255, 0);
+        let pool = Pool::in_subnet(address(1), mask, 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: address(2),
+                size: 16
+            }
+        );
+        // the network address itself is not handed out either
+        let pool = Pool::in_subnet(address(0), mask, 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: address(1),
+                size: 16
+            }
+        );
+        // just enough room before the broadcast address
+        let pool = Pool::in_subnet(address(238), mask, 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: address(239),
+                size: 16
+            }
+        );
+    }
+
+    #[test]
+    fn pool_before_server_at_end_of_subnet() {
+        let mask = Ipv4Addr::new(255, 255, 255, 0);
+        for server in [250, 254, 255] {
+            let pool = Pool::in_subnet(address(server), mask, 16);
+            assert_eq!(
+                pool,
+                Pool {
+                    start: address(server - 16),
+                    size: 16
+                }
+            );
+            assert!(!pool.contains(address(server)));
+            assert!(!pool.contains(address(255)));
+        }
+
+        let server = Ipv4Addr::new(255, 255, 255, 255);
+        let pool = Pool::in_subnet(server, mask, 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: Ipv4Addr::new(255, 255, 255, 239),
+                size: 16
+            }
+        );
+        assert!(!pool.contains(server));
+    }
+
+    #[test]
+    fn pool_shrinks_to_fit_subnet() {
+        // a /28 has 14 hosts, one of them the server
+        let mask = Ipv4Addr::new(255, 255, 255, 240);
+        let pool = Pool::in_subnet(address(1), mask, 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: address(2),
+                size: 13
+            }
+        );
+        // more room before the server than after it
+        let pool = Pool::in_subnet(address(10), mask, 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: address(1),
+                size: 9
+            }
+        );
+
+        let pool = Pool::in_subnet(address(1), Ipv4Addr::new(255, 255, 255, 252), 16);
+        assert_eq!(
+            pool,
+            Pool {
+                start: address(2),
+                size: 1
+            }
+        );
+        // no addresses left for clients
+        let server = Ipv4Addr::new(255, 255, 255, 255);
+        assert_eq!(Pool::in_subnet(server, server, 16).size, 0);
+    }
+}
@@ -0,0 +1,121 @@
+use trouble_host::prelude::*;
+
+// The GATT server of the `06_ble` example: one service with a writable led characteristic and a button characteristic
+// that notifies on every press and release. Both values are a single byte, 0 for off / released and 1 for on / pressed.
+// This module only depends on trouble-host so the attribute table and the checks on writes are tested on the host.
+
+pub const LED_SERVICE_UUID: u128 = 0x6e4b0001_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+pub const LED_UUID: u128 = 0x6e4b0002_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+pub const BUTTON_UUID: u128 = 0x6e4b0003_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+
+pub const CONNECTIONS_MAX: usize = 1;
+
+#[gatt_server(connections_max = CONNECTIONS_MAX)]
+pub struct LedServer {
+    pub led_button: LedButtonService,
+}
+
+#[gatt_service(uuid = LED_SERVICE_UUID)]
+pub struct LedButtonService {
+    #[characteristic(uuid = LED_UUID, read, write, value = 0)]
+    pub led: u8,
+    #[characteristic(uuid = BUTTON_UUID, read, notify, value = 0)]
+    pub button: u8,
+}
+
+impl<'a> LedServer<'a> {
+    // `name` is the GAP device name, at most 22 bytes. trouble-host keeps the name in a static so this can only be called
+    // once per boot.
+    pub fn with_name(name: &'a str) -> Result<Self, &'static str> {
+        Self::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+            name,
+            appearance: &appearance::light_source::GENERIC_LIGHT_SOURCE,
+        }))
+    }
+
+    // Checks a write before it is accepted, returns the new led state if the led was written.
+    // Writes to any other characteristic are left to the attribute server which rejects read only values.
+    pub fn check_write(&self, handle: u16, data: &[u8]) -> Result<Option<bool>, AttErrorCode> {
+        if handle == self.led_button.led.handle {
+            parse_switch(data).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn set_button(&self, pressed: bool) -> Result<(), Error> {
+        self.set(&self.led_button.button, &u8::from(pressed))
+    }
+
+    // sets the button value and sends it to `conn` if notifications are enabled
+    pub async fn notify_button(
+        &self,
+        conn: &GattConnection<'_, '_, DefaultPacketPool>,
+        pressed: bool,
+    ) -> Result<(), Error> {
+        self.led_button
+            .button
+            .notify(conn, &u8::from(pressed))
+            .await
+    }
+}
+
+// a single byte, 0 or 1
+pub fn parse_switch(data: &[u8]) -> Result<bool, AttErrorCode> {
+    match data {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        [_] => Err(AttErrorCode::VALUE_NOT_ALLOWED),
+        _ => Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_switch() {
+        assert_eq!(parse_switch(&[0]), Ok(false));
+        assert_eq!(parse_switch(&[1]), Ok(true));
+        assert_eq!(parse_switch(&[2]), Err(AttErrorCode::VALUE_NOT_ALLOWED));
+        assert_eq!(parse_switch(b"1"), Err(AttErrorCode::VALUE_NOT_ALLOWED));
+        assert_eq!(
+            parse_switch(&[]),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+        assert_eq!(
+            parse_switch(&[1, 0]),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+    }
+
+    // without the GAP service of `with_name`, which can only be added once per process
+    #[test]
+    fn server() {
+        let server = LedServer::new(AttributeTable::new());
+        let led = &server.led_button.led;
+        let button = &server.led_button.button;
+
+        // the led is written by the client, the button is notified
+        assert_ne!(led.handle, button.handle);
+        assert!(led.cccd_handle.is_none());
+        assert!(button.cccd_handle.is_some());
+        assert_eq!(server.get(led), Ok(0));
+        assert_eq!(server.get(button), Ok(0));
+
+        assert_eq!(server.check_write(led.handle, &[1]), Ok(Some(true)));
+        assert_eq!(server.check_write(led.handle, &[0]), Ok(Some(false)));
+        assert_eq!(
+            server.check_write(led.handle, &[1, 1]),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+        // left to the attribute server, which rejects writes to the read only button
+        assert_eq!(server.check_write(button.handle, &[7]), Ok(None));
+
+        server.set_button(true).unwrap();
+        assert_eq!(server.get(button), Ok(1));
+        server.set_button(false).unwrap();
+        assert_eq!(server.get(button), Ok(0));
+    }
+}
@@ -0,0 +1,18 @@
+#![cfg_attr(not(test), no_std)]
+
+// Protocol and parsing code used by the rp-pico2w-examples firmware. Nothing here depends on the chip or the radio,
+// so the tests run on a PC: `cargo test -p pico2w-logic --target x86_64-unknown-linux-gnu` (the `--target` is needed
+// because `.cargo/config.toml` builds for the pico by default). The firmware re-exports these modules under the
+// same names, e.g. `rp_pico2w_examples::dhcp`.
+
+pub mod command_protocol;
+pub mod dhcp;
+pub mod dhcp_leases;
+pub mod led_service;
+pub mod line_buffer;
+pub mod log_filter;
+pub mod log_format;
+pub mod provisioning_service;
+pub mod rfc5424;
+pub mod wifi_credentials;
+pub mod wifi_scan;
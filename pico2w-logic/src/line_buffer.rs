@@ -0,0 +1,233 @@
+use core::str;
+
+use heapless::Vec;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineError {
+    // the line did not fit in the buffer and was discarded
+    TooLong,
+}
+
+// Reassembles lines from serial port input that may arrive in arbitrary chunks.
+// Lines end with CR, LF or CRLF, backspace / delete remove the last character and
+// bytes that are not valid UTF-8 are replaced with '?' rather than dropping the line.
+pub struct LineBuffer<const N: usize> {
+    buf: Vec<u8, N>,
+    overflow: bool,
+    last_was_cr: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            overflow: false,
+            last_was_cr: false,
+        }
+    }
+
+    // feeds received bytes into the buffer, calling `on_line` for every completed line
+    pub fn feed(&mut self, data: &[u8], mut on_line: impl FnMut(Result<&str, LineError>)) {
+        for &b in data {
+            let last_was_cr = self.last_was_cr;
+            self.last_was_cr = b == b'\r';
+
+            match b {
+                // the LF of a CRLF pair has already ended the line
+                b'\n' if last_was_cr => {}
+                b'\r' | b'\n' => {
+                    if self.overflow {
+                        on_line(Err(LineError::TooLong));
+                    } else {
+                        on_line(Ok(sanitize(&mut self.buf)));
+                    }
+                    self.clear();
+                }
+                BACKSPACE | DELETE => self.backspace(),
+                b'\t' => self.push(b' '),
+                // ignore other control characters (e.g. escape sequences from arrow keys)
+                0..=0x1f => {}
+                _ => self.push(b),
+            }
+        }
+    }
+
+    // the incomplete line received so far
+    pub fn pending(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.overflow = false;
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.buf.push(b).is_err() {
+            self.overflow = true;
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.overflow {
+            return;
+        }
+
+        // remove a whole character, not just the last byte of a multi-byte UTF-8 sequence
+        while let Some(b) = self.buf.pop() {
+            if b & 0xc0 != 0x80 {
+                break;
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// replaces invalid UTF-8 bytes in place so that the line can always be returned as a str
+fn sanitize(buf: &mut [u8]) -> &str {
+    let mut start = 0;
+    while let Err(e) = str::from_utf8(&buf[start..]) {
+        let invalid = start + e.valid_up_to();
+        let len = e.error_len().unwrap_or(buf.len() - invalid);
+        buf[invalid..invalid + len].fill(b'?');
+        start = invalid + len;
+    }
+
+    // every invalid byte has been replaced with ASCII above
+    str::from_utf8(buf).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{string::String, vec::Vec};
+
+    fn lines<const N: usize>(
+        buffer: &mut LineBuffer<N>,
+        chunks: &[&[u8]],
+    ) -> Vec<Result<String, LineError>> {
+        let mut lines = Vec::new();
+        for chunk in chunks {
+            buffer.feed(chunk, |line| lines.push(line.map(String::from)));
+        }
+        lines
+    }
+
+    #[test]
+    fn ends_lines_with_cr_lf_and_crlf() {
+        let mut buffer = LineBuffer::<32>::new();
+        assert_eq!(
+            lines(&mut buffer, &[b"cr\rlf\ncrlf\r\n"]),
+            [Ok("cr".into()), Ok("lf".into()), Ok("crlf".into())]
+        );
+        assert!(buffer.pending().is_empty());
+    }
+
+    #[test]
+    fn ends_lines_split_across_chunks() {
+        let mut buffer = LineBuffer::<32>::new();
+        assert_eq!(
+            lines(
+                &mut buffer,
+                &[b"he", b"llo\r", b"\nwor", b"ld\n", b"cr", b"\r"]
+            ),
+            [Ok("hello".into()), Ok("world".into()), Ok("cr".into())]
+        );
+
+        // the CRLF pair split between two chunks ends just one line
+        assert_eq!(
+            lines(&mut buffer, &[b"a\r", b"\n", b"b\n"]),
+            [Ok("a".into()), Ok("b".into())]
+        );
+    }
+
+    #[test]
+    fn keeps_empty_lines() {
+        let mut buffer = LineBuffer::<32>::new();
+        assert_eq!(
+            lines(&mut buffer, &[b"\n\r\r\n\n"]),
+            [Ok("".into()), Ok("".into()), Ok("".into()), Ok("".into())]
+        );
+    }
+
+    #[test]
+    fn keeps_pending_input() {
+        let mut buffer = LineBuffer::<32>::new();
+        assert_eq!(lines(&mut buffer, &[b"led o"]), []);
+        assert_eq!(buffer.pending(), b"led o");
+        buffer.clear();
+        assert_eq!(lines(&mut buffer, &[b"n\n"]), [Ok("n".into())]);
+    }
+
+    #[test]
+    fn removes_characters_with_backspace_and_delete() {
+        let mut buffer = LineBuffer::<32>::new();
+        assert_eq!(
+            lines(&mut buffer, &[b"lex\x08d o\x7fon\n"]),
+            [Ok("led on".into())]
+        );
+        // nothing to remove
+        assert_eq!(lines(&mut buffer, &[b"\x08\x08ok\n"]), [Ok("ok".into())]);
+    }
+
+    #[test]
+    fn removes_multi_byte_characters_with_backspace() {
+        let mut buffer = LineBuffer::<32>::new();
+        let input = "caf\u{e9}\u{1f600}\x08!\x08\x08\n";
+        assert_eq!(lines(&mut buffer, &[input.as_bytes()]), [Ok("caf".into())]);
+
+        // the bytes of the character arrive in separate chunks
+        let euro = "\u{20ac}".as_bytes();
+        assert_eq!(
+            lines(&mut buffer, &[b"1", &euro[..1], &euro[1..], b"\x08\n"]),
+            [Ok("1".into())]
+        );
+    }
+
+    #[test]
+    fn replaces_tabs_and_drops_control_characters() {
+        let mut buffer = LineBuffer::<32>::new();
+        // a tab and the escape sequence of the up arrow key
+        assert_eq!(lines(&mut buffer, &[b"a\tb\x1b[A\n"]), [Ok("a b[A".into())]);
+    }
+
+    #[test]
+    fn rejects_long_lines_and_recovers() {
+        let mut buffer = LineBuffer::<8>::new();
+        assert_eq!(
+            lines(&mut buffer, &[b"12345678\n"]),
+            [Ok("12345678".into())]
+        );
+        // backspace does not bring a discarded line back
+        assert_eq!(
+            lines(&mut buffer, &[b"12345", b"6789", b"\x08\x08\n", b"ok\n"]),
+            [Err(LineError::TooLong), Ok("ok".into())]
+        );
+        assert!(buffer.pending().is_empty());
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        let mut buffer = LineBuffer::<32>::new();
+        assert_eq!(
+            lines(&mut buffer, &[b"a\xffb\xc3(\n"]),
+            [Ok("a?b?(".into())]
+        );
+        // a multi-byte sequence cut off by the end of the line
+        assert_eq!(lines(&mut buffer, &[b"x\xe2\x82\n"]), [Ok("x??".into())]);
+        // an encoded surrogate is not valid UTF-8 either
+        assert_eq!(
+            lines(&mut buffer, &[b"\xed\xa0\x80\xc3\xa9\n"]),
+            [Ok("???\u{e9}".into())]
+        );
+    }
+}
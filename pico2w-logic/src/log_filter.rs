@@ -0,0 +1,258 @@
+use core::fmt;
+
+use heapless::{String, Vec};
+use log::{Level, LevelFilter};
+
+const MAX_DIRECTIVES: usize = 8;
+const MAX_MODULE_LEN: usize = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FilterError {
+    InvalidLevel,
+    TooManyDirectives,
+    ModuleTooLong,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLevel => f.write_str("invalid log level"),
+            Self::TooManyDirectives => write!(f, "more than {MAX_DIRECTIVES} module filters"),
+            Self::ModuleTooLong => write!(f, "module path longer than {MAX_MODULE_LEN} bytes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Directive {
+    module: String<MAX_MODULE_LEN>,
+    level: LevelFilter,
+}
+
+// A log filter in the same format as `RUST_LOG`, for example `info,rp_pico2w_examples::network=debug`.
+// Records are filtered by the directive with the longest module path matching their target,
+// falling back to the default level when no directive matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    directives: Vec<Directive, MAX_DIRECTIVES>,
+}
+
+impl LogFilter {
+    pub const fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            directives: Vec::new(),
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, FilterError> {
+        let mut filter = Self::new(LevelFilter::Error);
+
+        for part in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            match part.split_once('=') {
+                Some((module, level)) => {
+                    let level = parse_level(level)?;
+                    filter.set_module(module.trim(), level)?;
+                }
+                None => match parse_level(part) {
+                    Ok(level) => filter.default = level,
+                    // a module path on its own enables all of its logs
+                    Err(_) => filter.set_module(part, LevelFilter::Trace)?,
+                },
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn set_module(&mut self, module: &str, level: LevelFilter) -> Result<(), FilterError> {
+        if let Some(directive) = self.directives.iter_mut().find(|d| d.module == module) {
+            directive.level = level;
+            return Ok(());
+        }
+
+        let module = String::try_from(module).map_err(|_| FilterError::ModuleTooLong)?;
+        self.directives
+            .push(Directive { module, level })
+            .map_err(|_| FilterError::TooManyDirectives)
+    }
+
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        level <= self.level_for(target)
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|d| matches_module(target, &d.module))
+            .max_by_key(|d| d.module.len())
+            .map_or(self.default, |d| d.level)
+    }
+
+    // the most verbose level any record can be logged at
+    pub fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|d| d.level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(level_name(self.default))?;
+        for d in self.directives.iter() {
+            write!(f, ",{}={}", d.module, level_name(d.level))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, FilterError> {
+    level.trim().parse().map_err(|_| FilterError::InvalidLevel)
+}
+
+// `rp_pico2w_examples::network` matches itself and its submodules but not `rp_pico2w_examples::networking`
+fn matches_module(target: &str, module: &str) -> bool {
+    match target.strip_prefix(module) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+fn level_name(level: LevelFilter) -> &'static str {
+    match level {
+        LevelFilter::Off => "off",
+        LevelFilter::Error => "error",
+        LevelFilter::Warn => "warn",
+        LevelFilter::Info => "info",
+        LevelFilter::Debug => "debug",
+        LevelFilter::Trace => "trace",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::string::ToString;
+
+    #[test]
+    fn parses_default_level() {
+        let filter = LogFilter::parse("debug").unwrap();
+        assert_eq!(filter.level_for("rp_pico2w_examples"), LevelFilter::Debug);
+        assert_eq!(filter.max_level(), LevelFilter::Debug);
+        assert_eq!(filter.to_string(), "debug");
+
+        // case and spaces do not matter
+        let filter = LogFilter::parse(" WARN ").unwrap();
+        assert_eq!(filter.level_for("cyw43"), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn parses_module_levels() {
+        let filter =
+            LogFilter::parse("info, cyw43=off ,rp_pico2w_examples::network=trace").unwrap();
+        assert_eq!(filter.level_for("embassy_net"), LevelFilter::Info);
+        assert_eq!(filter.level_for("cyw43"), LevelFilter::Off);
+        assert_eq!(
+            filter.level_for("rp_pico2w_examples::network"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.max_level(), LevelFilter::Trace);
+        assert_eq!(
+            filter.to_string(),
+            "info,cyw43=off,rp_pico2w_examples::network=trace"
+        );
+        assert_eq!(LogFilter::parse(&filter.to_string()), Ok(filter));
+    }
+
+    #[test]
+    fn parses_module_without_level() {
+        let filter = LogFilter::parse("rp_pico2w_examples").unwrap();
+        assert_eq!(filter.level_for("rp_pico2w_examples"), LevelFilter::Trace);
+        assert_eq!(filter.level_for("cyw43"), LevelFilter::Error);
+    }
+
+    #[test]
+    fn later_directives_win() {
+        let filter = LogFilter::parse("warn,cyw43=debug,info,cyw43=error").unwrap();
+        assert_eq!(filter.to_string(), "info,cyw43=error");
+    }
+
+    #[test]
+    fn parses_empty_spec() {
+        let expected = LogFilter::new(LevelFilter::Error);
+        assert_eq!(LogFilter::parse(""), Ok(expected.clone()));
+        assert_eq!(LogFilter::parse(" , ,"), Ok(expected));
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        assert_eq!(
+            LogFilter::parse("cyw43=loud"),
+            Err(FilterError::InvalidLevel)
+        );
+        assert_eq!(LogFilter::parse("cyw43="), Err(FilterError::InvalidLevel));
+        assert_eq!(
+            LogFilter::parse(&"m".repeat(MAX_MODULE_LEN + 1)),
+            Err(FilterError::ModuleTooLong)
+        );
+        assert_eq!(
+            LogFilter::parse("a,b,c,d,e,f,g,h,i"),
+            Err(FilterError::TooManyDirectives)
+        );
+        assert!(LogFilter::parse("a,b,c,d,e,f,g,h").is_ok());
+    }
+
+    #[test]
+    fn uses_longest_matching_module() {
+        let filter = LogFilter::parse(
+            "warn,rp_pico2w_examples::network::dhcp=trace,rp_pico2w_examples=info,rp_pico2w_examples::network=debug",
+        )
+        .unwrap();
+        assert_eq!(filter.level_for("rp_pico2w_examples"), LevelFilter::Info);
+        assert_eq!(
+            filter.level_for("rp_pico2w_examples::shell"),
+            LevelFilter::Info
+        );
+        assert_eq!(
+            filter.level_for("rp_pico2w_examples::network"),
+            LevelFilter::Debug
+        );
+        assert_eq!(
+            filter.level_for("rp_pico2w_examples::network::dhcp::leases"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.level_for("embassy_net"), LevelFilter::Warn);
+
+        assert!(filter.enabled("rp_pico2w_examples::network", Level::Debug));
+        assert!(!filter.enabled("rp_pico2w_examples::network", Level::Trace));
+        assert!(!filter.enabled("embassy_net", Level::Info));
+    }
+
+    #[test]
+    fn matches_whole_modules_only() {
+        assert!(matches_module("cyw43", "cyw43"));
+        assert!(matches_module("cyw43::control", "cyw43"));
+        assert!(!matches_module("cyw43_pio", "cyw43"));
+        assert!(!matches_module("cyw4", "cyw43"));
+        assert!(!matches_module(
+            "rp_pico2w_examples::networking",
+            "rp_pico2w_examples::network"
+        ));
+
+        let filter = LogFilter::parse("error,rp_pico2w_examples::network=debug").unwrap();
+        assert_eq!(
+            filter.level_for("rp_pico2w_examples::networking"),
+            LevelFilter::Error
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use core::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use heapless::String;
+use log::Level;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    MissingHeader,
+    InvalidUptime,
+    InvalidLevel,
+    MissingModule,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => f.write_str("line does not start with '['"),
+            Self::InvalidUptime => f.write_str("invalid uptime"),
+            Self::InvalidLevel => f.write_str("invalid log level"),
+            Self::MissingModule => f.write_str("missing module"),
+        }
+    }
+}
+
+// a log line split back into its parts by `parse`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogLine<'a> {
+    pub uptime_ms: u64,
+    pub level: Level,
+    pub module: &'a str,
+    pub task: Option<&'a str>,
+    pub message: &'a str,
+}
+
+// Writes a log record as a single line (without the line ending), for example:
+// `[12.345 INFO  rp_pico2w_examples::network] joined network`
+// `[12.402 DEBUG rp_pico2w_examples::network #wifi] link up`
+// The uptime is in seconds with millisecond resolution. The optional task tag comes from the log target,
+// e.g. `info!(target: "wifi", ...)`, and is only written when it differs from the module.
+pub fn write_line(
+    out: &mut impl fmt::Write,
+    uptime_ms: u64,
+    level: Level,
+    module: &str,
+    task: Option<&str>,
+    message: fmt::Arguments<'_>,
+) -> fmt::Result {
+    write!(
+        out,
+        "[{}.{:03} {:<5} {}",
+        uptime_ms / 1000,
+        uptime_ms % 1000,
+        level,
+        module
+    )?;
+
+    if let Some(task) = task {
+        write!(out, " #{task}")?;
+    }
+
+    out.write_str("] ")?;
+    out.write_fmt(message)
+}
+
+// Like `write_line` but into a fixed size buffer and with the line ending, which is always written: a record that does
+// not fit is cut off at a character boundary so that the next record still starts on a new line
+pub fn write_record<const N: usize>(
+    line: &mut String<N>,
+    uptime_ms: u64,
+    level: Level,
+    module: &str,
+    task: Option<&str>,
+    message: fmt::Arguments<'_>,
+) {
+    let mut out = Truncate {
+        line,
+        limit: N.saturating_sub(LINE_ENDING.len()),
+    };
+    // an error only means that the record was cut off
+    let _ = write_line(&mut out, uptime_ms, level, module, task, message);
+    let _ = line.push_str(LINE_ENDING);
+}
+
+const LINE_ENDING: &str = "\r\n";
+
+// stops at `limit` bytes, writing as much of the piece that does not fit as it can
+struct Truncate<'a, const N: usize> {
+    line: &'a mut String<N>,
+    limit: usize,
+}
+
+impl<const N: usize> Write for Truncate<'_, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.line.len() + c.len_utf8() > self.limit {
+                return Err(fmt::Error);
+            }
+            let _ = self.line.push(c);
+        }
+
+        Ok(())
+    }
+}
+
+// Parses a line written by `write_line`, for host tools reading the serial port
+pub fn parse(line: &str) -> Result<LogLine<'_>, ParseError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let rest = line.strip_prefix('[').ok_or(ParseError::MissingHeader)?;
+    let (header, message) = rest.split_once("] ").ok_or(ParseError::MissingHeader)?;
+
+    let mut fields = header.split_ascii_whitespace();
+    let uptime_ms = fields
+        .next()
+        .and_then(parse_uptime)
+        .ok_or(ParseError::InvalidUptime)?;
+    let level = fields
+        .next()
+        .and_then(|level| Level::from_str(level).ok())
+        .ok_or(ParseError::InvalidLevel)?;
+    let module = fields.next().ok_or(ParseError::MissingModule)?;
+    let task = fields.next().and_then(|task| task.strip_prefix('#'));
+
+    Ok(LogLine {
+        uptime_ms,
+        level,
+        module,
+        task,
+        message,
+    })
+}
+
+// `<seconds>.<milliseconds>`, the milliseconds always have three digits
+fn parse_uptime(uptime: &str) -> Option<u64> {
+    let (secs, millis) = uptime.split_once('.')?;
+    if millis.len() != 3 {
+        return None;
+    }
+
+    let secs: u64 = secs.parse().ok()?;
+    let millis: u64 = millis.parse().ok()?;
+    secs.checked_mul(1000)?.checked_add(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::string::String;
+
+    fn line(uptime_ms: u64, level: Level, task: Option<&str>, message: &str) -> String {
+        let mut line = String::new();
+        write_line(
+            &mut line,
+            uptime_ms,
+            level,
+            "rp_pico2w_examples::network",
+            task,
+            format_args!("{message}"),
+        )
+        .unwrap();
+        line
+    }
+
+    #[test]
+    fn writes_lines() {
+        assert_eq!(
+            line(12345, Level::Info, None, "joined network"),
+            "[12.345 INFO  rp_pico2w_examples::network] joined network"
+        );
+        assert_eq!(
+            line(12402, Level::Debug, Some("wifi"), "link up"),
+            "[12.402 DEBUG rp_pico2w_examples::network #wifi] link up"
+        );
+        assert_eq!(
+            line(7, Level::Error, None, ""),
+            "[0.007 ERROR rp_pico2w_examples::network] "
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for task in [None, Some("wifi")] {
+            for (uptime_ms, level) in [
+                (0, Level::Trace),
+                (999, Level::Warn),
+                (u64::MAX, Level::Error),
+            ] {
+                let line = line(uptime_ms, level, task, "joined network");
+                assert_eq!(
+                    parse(&line),
+                    Ok(LogLine {
+                        uptime_ms,
+                        level,
+                        module: "rp_pico2w_examples::network",
+                        task,
+                        message: "joined network",
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_message_with_separator() {
+        for message in ["[1.000 INFO  x] ] #wifi] ", "] ", "a]b", ""] {
+            for task in [None, Some("wifi")] {
+                let line = line(1500, Level::Info, task, message);
+                let parsed = parse(&line).unwrap();
+                assert_eq!(parsed.task, task);
+                assert_eq!(parsed.message, message);
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_line_endings() {
+        let line = line(1500, Level::Info, None, "up") + "\r\n";
+        assert_eq!(parse(&line).unwrap().message, "up");
+    }
+
+    #[test]
+    fn writes_records_with_line_ending() {
+        let mut record: heapless::String<64> = heapless::String::new();
+        write_record(
+            &mut record,
+            1500,
+            Level::Info,
+            "network",
+            Some("wifi"),
+            format_args!("up"),
+        );
+        assert_eq!(record, "[1.500 INFO  network #wifi] up\r\n");
+    }
+
+    #[test]
+    fn truncates_long_records_but_keeps_line_ending() {
+        let message = "\u{e9}".repeat(100);
+        let mut record: heapless::String<64> = heapless::String::new();
+        write_record(
+            &mut record,
+            1500,
+            Level::Info,
+            "network",
+            None,
+            format_args!("{message}"),
+        );
+        assert_eq!(record.len(), 64);
+        assert!(record.ends_with("\u{e9}\r\n"));
+
+        // the cut off line still parses, with 20 of the 100 characters left
+        assert_eq!(parse(&record).unwrap().message, "\u{e9}".repeat(20));
+
+        // even the header does not fit
+        let mut record: heapless::String<8> = heapless::String::new();
+        write_record(
+            &mut record,
+            1500,
+            Level::Info,
+            "network",
+            None,
+            format_args!("up"),
+        );
+        assert_eq!(record, "[1.500\r\n");
+    }
+
+    #[test]
+    fn rejects_broken_lines() {
+        assert_eq!(parse("joined network"), Err(ParseError::MissingHeader));
+        assert_eq!(
+            parse("[1.000 INFO  network"),
+            Err(ParseError::MissingHeader)
+        );
+        assert_eq!(parse("[] x"), Err(ParseError::InvalidUptime));
+        assert_eq!(
+            parse("[1.5 INFO  network] x"),
+            Err(ParseError::InvalidUptime)
+        );
+        assert_eq!(
+            parse("[1.000 LOUD network] x"),
+            Err(ParseError::InvalidLevel)
+        );
+        assert_eq!(parse("[1.000 INFO ] x"), Err(ParseError::MissingModule));
+    }
+}
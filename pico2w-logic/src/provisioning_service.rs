@@ -0,0 +1,195 @@
+use trouble_host::prelude::*;
+
+use crate::wifi_credentials::{
+    CredentialsError, WifiCredentials, JOIN_STATUS_NO_NETWORKS, MAX_PASSWORD_LEN, MAX_SSID_LEN,
+};
+
+// The GATT server used to provision the Wi-Fi credentials (see `provisioning.rs` in the firmware). A phone writes the ssid and the
+// password, then writes 1 to `connect`. The board tries to join the network and reports the outcome in `status`,
+// which is also notified. The password cannot be read back.
+// This module only depends on trouble-host so the attribute table and the checks on writes are tested on the host.
+
+pub const PROVISIONING_SERVICE_UUID: u128 = 0x6e4b0101_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+pub const SSID_UUID: u128 = 0x6e4b0102_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+pub const PASSWORD_UUID: u128 = 0x6e4b0103_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+pub const CONNECT_UUID: u128 = 0x6e4b0104_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+pub const STATUS_UUID: u128 = 0x6e4b0105_7c2a_4f3e_9d1b_2a5c8f0e3b71;
+
+pub const CONNECTIONS_MAX: usize = 1;
+
+#[gatt_server(connections_max = CONNECTIONS_MAX)]
+pub struct ProvisioningServer {
+    pub provisioning: ProvisioningService,
+}
+
+#[gatt_service(uuid = PROVISIONING_SERVICE_UUID)]
+pub struct ProvisioningService {
+    #[characteristic(uuid = SSID_UUID, read, write)]
+    pub ssid: HeaplessString<MAX_SSID_LEN>,
+    #[characteristic(uuid = PASSWORD_UUID, write)]
+    pub password: HeaplessString<MAX_PASSWORD_LEN>,
+    #[characteristic(uuid = CONNECT_UUID, write, value = 0)]
+    pub connect: u8,
+    #[characteristic(uuid = STATUS_UUID, read, notify, value = 0)]
+    pub status: u8,
+}
+
+// the value of the status characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Status {
+    Idle = 0,
+    Connecting = 1,
+    // joined the network and saved the credentials
+    Provisioned = 2,
+    InvalidCredentials = 3,
+    NetworkNotFound = 4,
+    // not reported yet, the radio driver does not tell a wrong password apart from other failures
+    AuthenticationFailed = 5,
+    JoinFailed = 6,
+    SaveFailed = 7,
+}
+
+impl Status {
+    // from the status code of a failed `Control::join`
+    pub fn from_join_status(status: u32) -> Self {
+        match status {
+            JOIN_STATUS_NO_NETWORKS => Self::NetworkNotFound,
+            _ => Self::JoinFailed,
+        }
+    }
+}
+
+impl<'a> ProvisioningServer<'a> {
+    // `name` is the GAP device name, at most 22 bytes. trouble-host keeps the name in a static so this can only be called
+    // once per boot.
+    pub fn with_name(name: &'a str) -> Result<Self, &'static str> {
+        Self::new_with_config(GapConfig::Peripheral(PeripheralConfig {
+            name,
+            appearance: &appearance::UNKNOWN,
+        }))
+    }
+
+    // Checks a write before it is accepted, returns true if it asks to join the network.
+    // Writes to any other characteristic are left to the attribute server which rejects read only values.
+    pub fn check_write(&self, handle: u16, data: &[u8]) -> Result<bool, AttErrorCode> {
+        let service = &self.provisioning;
+        if handle == service.ssid.handle {
+            check_text(data, 1, MAX_SSID_LEN)?;
+        } else if handle == service.password.handle {
+            check_text(data, 0, MAX_PASSWORD_LEN)?;
+        } else if handle == service.connect.handle {
+            return match data {
+                [1] => Ok(true),
+                [_] => Err(AttErrorCode::VALUE_NOT_ALLOWED),
+                _ => Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH),
+            };
+        }
+        Ok(false)
+    }
+
+    // the ssid and password written so far
+    pub fn credentials(&self) -> Result<WifiCredentials, CredentialsError> {
+        let service = &self.provisioning;
+        let ssid = self
+            .get(&service.ssid)
+            .map_err(|_| CredentialsError::NotUtf8)?;
+        let password = self
+            .get(&service.password)
+            .map_err(|_| CredentialsError::NotUtf8)?;
+        WifiCredentials::new(&ssid, &password)
+    }
+
+    // sets the status and sends it to `conn` if notifications are enabled
+    pub async fn notify_status(
+        &self,
+        conn: &GattConnection<'_, '_, DefaultPacketPool>,
+        status: Status,
+    ) -> Result<(), Error> {
+        self.provisioning.status.notify(conn, &(status as u8)).await
+    }
+}
+
+// utf-8 of `min` to `max` bytes
+fn check_text(data: &[u8], min: usize, max: usize) -> Result<(), AttErrorCode> {
+    if !(min..=max).contains(&data.len()) {
+        Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+    } else if core::str::from_utf8(data).is_err() {
+        Err(AttErrorCode::VALUE_NOT_ALLOWED)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wifi_credentials::{JOIN_STATUS_FAIL, JOIN_STATUS_TIMEOUT};
+
+    #[test]
+    fn maps_join_status() {
+        assert_eq!(
+            Status::from_join_status(JOIN_STATUS_NO_NETWORKS),
+            Status::NetworkNotFound
+        );
+        assert_eq!(
+            Status::from_join_status(JOIN_STATUS_TIMEOUT),
+            Status::JoinFailed
+        );
+        assert_eq!(
+            Status::from_join_status(JOIN_STATUS_FAIL),
+            Status::JoinFailed
+        );
+    }
+
+    // without the GAP service of `with_name`, which can only be added once per process
+    #[test]
+    fn server() {
+        let server = ProvisioningServer::new(AttributeTable::new());
+        let service = &server.provisioning;
+
+        assert_eq!(server.check_write(service.ssid.handle, b"home"), Ok(false));
+        assert_eq!(
+            server.check_write(service.ssid.handle, b""),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+        assert_eq!(
+            server.check_write(service.ssid.handle, &[b'x'; 33]),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+        assert_eq!(
+            server.check_write(service.ssid.handle, &[0xff]),
+            Err(AttErrorCode::VALUE_NOT_ALLOWED)
+        );
+        assert_eq!(server.check_write(service.password.handle, b""), Ok(false));
+        assert_eq!(
+            server.check_write(service.password.handle, &[b'x'; 65]),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+        assert_eq!(server.check_write(service.connect.handle, &[1]), Ok(true));
+        assert_eq!(
+            server.check_write(service.connect.handle, &[0]),
+            Err(AttErrorCode::VALUE_NOT_ALLOWED)
+        );
+        assert!(service.status.cccd_handle.is_some());
+
+        // nothing written yet
+        assert_eq!(server.credentials(), Err(CredentialsError::SsidLength));
+        let ssid = HeaplessString::<MAX_SSID_LEN>::try_from("home").unwrap();
+        server.set(&service.ssid, &ssid).unwrap();
+        assert_eq!(
+            server.credentials(),
+            Ok(WifiCredentials::new("home", "").unwrap())
+        );
+        let password = HeaplessString::<MAX_PASSWORD_LEN>::try_from("correct horse").unwrap();
+        server.set(&service.password, &password).unwrap();
+        assert_eq!(
+            server.credentials(),
+            Ok(WifiCredentials::new("home", "correct horse").unwrap())
+        );
+        // fits the characteristic but is no valid password
+        let password = HeaplessString::<MAX_PASSWORD_LEN>::try_from("short").unwrap();
+        server.set(&service.password, &password).unwrap();
+        assert_eq!(server.credentials(), Err(CredentialsError::PasswordLength));
+    }
+}
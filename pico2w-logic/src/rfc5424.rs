@@ -0,0 +1,177 @@
+use core::fmt::{self, Write};
+
+use log::Level;
+
+// local0, see section 6.2.1 of RFC 5424
+const FACILITY_LOCAL0: u8 = 16;
+
+// the private enterprise number reserved for documentation (RFC 5612), used to name our structured data
+const ENTERPRISE_NUMBER: u32 = 32473;
+
+const MAX_HOSTNAME_LEN: usize = 255;
+const MAX_APP_NAME_LEN: usize = 48;
+
+pub struct Header<'a> {
+    pub hostname: &'a str,
+    pub app_name: &'a str,
+}
+
+// Writes a log record as an RFC 5424 syslog message, for example:
+// `<134>1 - pico2w rp-pico2w-examples - - [log@32473 uptime_ms="1234" module="rp_pico2w_examples::network"] connected`
+// The board has no wall clock so the timestamp is left out (`-`) and the uptime is sent as structured data instead.
+pub fn write_message(
+    out: &mut impl Write,
+    header: &Header<'_>,
+    level: Level,
+    uptime_ms: u64,
+    module: &str,
+    message: fmt::Arguments<'_>,
+) -> fmt::Result {
+    write!(out, "<{}>1 - ", priority(level))?;
+    write_header_field(out, header.hostname, MAX_HOSTNAME_LEN)?;
+    out.write_char(' ')?;
+    write_header_field(out, header.app_name, MAX_APP_NAME_LEN)?;
+    write!(
+        out,
+        " - - [log@{ENTERPRISE_NUMBER} uptime_ms=\"{uptime_ms}\" module=\""
+    )?;
+    write_param_value(out, module)?;
+    out.write_str("\"] ")?;
+    out.write_fmt(message)
+}
+
+pub fn priority(level: Level) -> u8 {
+    FACILITY_LOCAL0 * 8 + severity(level)
+}
+
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+// header fields are limited to printable ASCII without spaces, an empty field is written as the nil value `-`
+fn write_header_field(out: &mut impl Write, value: &str, max_len: usize) -> fmt::Result {
+    if value.is_empty() {
+        return out.write_char('-');
+    }
+
+    for c in value.chars().take(max_len) {
+        out.write_char(if c.is_ascii_graphic() { c } else { '_' })?;
+    }
+
+    Ok(())
+}
+
+// `"`, `\` and `]` must be escaped inside structured data parameter values
+fn write_param_value(out: &mut impl Write, value: &str) -> fmt::Result {
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            out.write_char('\\')?;
+        }
+        out.write_char(c)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::string::String;
+
+    fn message(header: &Header<'_>, level: Level, module: &str, text: &str) -> String {
+        let mut out = String::new();
+        write_message(
+            &mut out,
+            header,
+            level,
+            1234,
+            module,
+            format_args!("{text}"),
+        )
+        .unwrap();
+        out
+    }
+
+    const HEADER: Header<'static> = Header {
+        hostname: "pico2w",
+        app_name: "rp-pico2w-examples",
+    };
+
+    #[test]
+    fn calculates_priority() {
+        // facility local0 (16) * 8 + severity
+        assert_eq!(priority(Level::Error), 16 * 8 + 3);
+        assert_eq!(priority(Level::Warn), 16 * 8 + 4);
+        assert_eq!(priority(Level::Info), 16 * 8 + 6);
+        assert_eq!(priority(Level::Debug), 16 * 8 + 7);
+        assert_eq!(priority(Level::Trace), 16 * 8 + 7);
+    }
+
+    #[test]
+    fn writes_message() {
+        assert_eq!(
+            message(
+                &HEADER,
+                Level::Info,
+                "rp_pico2w_examples::network",
+                "connected"
+            ),
+            "<134>1 - pico2w rp-pico2w-examples - - \
+             [log@32473 uptime_ms=\"1234\" module=\"rp_pico2w_examples::network\"] connected"
+        );
+        assert!(message(&HEADER, Level::Error, "m", "").starts_with("<131>1 "));
+    }
+
+    #[test]
+    fn writes_nil_values() {
+        let header = Header {
+            hostname: "",
+            app_name: "",
+        };
+        // the timestamp, hostname, app name, procid and msgid
+        assert_eq!(
+            message(&header, Level::Warn, "m", "x"),
+            "<132>1 - - - - - [log@32473 uptime_ms=\"1234\" module=\"m\"] x"
+        );
+    }
+
+    #[test]
+    fn limits_header_fields() {
+        let hostname = "h".repeat(MAX_HOSTNAME_LEN + 10);
+        let app_name = "a".repeat(MAX_APP_NAME_LEN + 10);
+        let header = Header {
+            hostname: &hostname,
+            app_name: &app_name,
+        };
+        let out = message(&header, Level::Info, "m", "x");
+        let fields: std::vec::Vec<_> = out.split(' ').collect();
+        assert_eq!(fields[2], &hostname[..MAX_HOSTNAME_LEN]);
+        assert_eq!(fields[3], &app_name[..MAX_APP_NAME_LEN]);
+    }
+
+    #[test]
+    fn replaces_unprintable_header_characters() {
+        let header = Header {
+            hostname: "my pico\u{e9}",
+            app_name: "app\tname",
+        };
+        assert!(
+            message(&header, Level::Info, "m", "x").starts_with("<134>1 - my_pico_ app_name - - ")
+        );
+    }
+
+    #[test]
+    fn escapes_param_values() {
+        assert_eq!(
+            message(&HEADER, Level::Info, "a\"b\\c]d", "x \"y\" ]"),
+            "<134>1 - pico2w rp-pico2w-examples - - \
+             [log@32473 uptime_ms=\"1234\" module=\"a\\\"b\\\\c\\]d\"] x \"y\" ]"
+        );
+    }
+}
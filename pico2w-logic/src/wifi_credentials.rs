@@ -0,0 +1,227 @@
+use core::fmt;
+
+use heapless::String;
+
+// Wi-Fi credentials and the record they are saved as in flash by BLE provisioning (see `provisioning.rs` in the firmware):
+// magic, ssid length, password length, two reserved bytes, ssid, password and a checksum of everything before it.
+// Erased flash reads as 0xff so it never has the magic and a record cut short by a power cut fails the checksum.
+
+pub const MAX_SSID_LEN: usize = 32;
+// 8 to 63 characters for a passphrase or 64 hex digits for a pre-shared key, empty for an open network
+pub const MAX_PASSWORD_LEN: usize = 64;
+const MIN_PASSPHRASE_LEN: usize = 8;
+const MAX_PASSPHRASE_LEN: usize = 63;
+
+// The status of a failed join (`cyw43::ControlError::status`), cyw43 calls these `EStatus` but does not export them.
+// A wrong password is not told apart, it usually ends as `JOIN_STATUS_FAIL`.
+pub const JOIN_STATUS_FAIL: u32 = 1;
+pub const JOIN_STATUS_TIMEOUT: u32 = 2;
+// no network with the ssid answered
+pub const JOIN_STATUS_NO_NETWORKS: u32 = 3;
+
+const MAGIC: u32 = 0x5749_4649; // "WIFI"
+const HEADER_LEN: usize = 8;
+const CHECKSUM_OFFSET: usize = HEADER_LEN + MAX_SSID_LEN + MAX_PASSWORD_LEN;
+pub const RECORD_LEN: usize = CHECKSUM_OFFSET + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CredentialsError {
+    SsidLength,
+    PasswordLength,
+    NotUtf8,
+}
+
+impl CredentialsError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SsidLength => "ssid must be 1 to 32 bytes",
+            Self::PasswordLength => "password must be empty, 8 to 63 characters or 64 hex digits",
+            Self::NotUtf8 => "ssid and password must be utf-8",
+        }
+    }
+}
+
+impl fmt::Display for CredentialsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiCredentials {
+    ssid: String<MAX_SSID_LEN>,
+    password: String<MAX_PASSWORD_LEN>,
+}
+
+impl WifiCredentials {
+    pub fn new(ssid: &str, password: &str) -> Result<Self, CredentialsError> {
+        if ssid.is_empty() {
+            return Err(CredentialsError::SsidLength);
+        }
+        let passphrase = (MIN_PASSPHRASE_LEN..=MAX_PASSPHRASE_LEN).contains(&password.len());
+        if !(password.is_empty() || passphrase || is_psk(password)) {
+            return Err(CredentialsError::PasswordLength);
+        }
+
+        Ok(Self {
+            ssid: String::try_from(ssid).map_err(|_| CredentialsError::SsidLength)?,
+            password: String::try_from(password).map_err(|_| CredentialsError::PasswordLength)?,
+        })
+    }
+
+    // e.g. the values written to the GATT characteristics
+    pub fn from_bytes(ssid: &[u8], password: &[u8]) -> Result<Self, CredentialsError> {
+        let ssid = core::str::from_utf8(ssid).map_err(|_| CredentialsError::NotUtf8)?;
+        let password = core::str::from_utf8(password).map_err(|_| CredentialsError::NotUtf8)?;
+        Self::new(ssid, password)
+    }
+
+    pub fn ssid(&self) -> &str {
+        &self.ssid
+    }
+
+    // empty for an open network
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    // a password of 64 hex digits is the pre-shared key itself rather than a passphrase
+    pub fn is_psk(&self) -> bool {
+        is_psk(&self.password)
+    }
+
+    pub fn to_record(&self) -> [u8; RECORD_LEN] {
+        let mut record = [0; RECORD_LEN];
+        record[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        record[4] = self.ssid.len() as u8;
+        record[5] = self.password.len() as u8;
+
+        let ssid = HEADER_LEN;
+        let password = HEADER_LEN + MAX_SSID_LEN;
+        record[ssid..ssid + self.ssid.len()].copy_from_slice(self.ssid.as_bytes());
+        record[password..password + self.password.len()].copy_from_slice(self.password.as_bytes());
+
+        let checksum = checksum(&record[..CHECKSUM_OFFSET]);
+        record[CHECKSUM_OFFSET..].copy_from_slice(&checksum.to_le_bytes());
+        record
+    }
+
+    // `None` if `record` is not a valid record, e.g. erased flash
+    pub fn from_record(record: &[u8]) -> Option<Self> {
+        let record = record.get(..RECORD_LEN)?;
+        let (data, stored) = record.split_at(CHECKSUM_OFFSET);
+        if data[..4] != MAGIC.to_le_bytes() || stored != checksum(data).to_le_bytes() {
+            return None;
+        }
+
+        let ssid = HEADER_LEN;
+        let password = HEADER_LEN + MAX_SSID_LEN;
+        let ssid = data.get(ssid..ssid + data[4] as usize)?;
+        let password = data.get(password..password + data[5] as usize)?;
+        Self::from_bytes(ssid, password).ok()
+    }
+}
+
+fn is_psk(password: &str) -> bool {
+    password.len() == MAX_PASSWORD_LEN && password.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+// FNV-1a, like the crash report
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, b| {
+        (hash ^ *b as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PSK: &str = "0123456789abcdef0123456789ABCDEF0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn accepts_valid_passwords() {
+        let open = WifiCredentials::new("cafe", "").unwrap();
+        assert_eq!(open.password(), "");
+        assert!(!open.is_psk());
+
+        let shortest = WifiCredentials::new("home", "12345678").unwrap();
+        assert!(!shortest.is_psk());
+
+        let longest = WifiCredentials::new("home", &"p".repeat(63)).unwrap();
+        assert!(!longest.is_psk());
+
+        let psk = WifiCredentials::new("home", PSK).unwrap();
+        assert!(psk.is_psk());
+    }
+
+    #[test]
+    fn rejects_invalid_passwords() {
+        assert_eq!(
+            WifiCredentials::new("home", "1234567"),
+            Err(CredentialsError::PasswordLength)
+        );
+        // too long for a passphrase and not a pre-shared key
+        assert_eq!(
+            WifiCredentials::new("home", &"p".repeat(64)),
+            Err(CredentialsError::PasswordLength)
+        );
+        let not_hex = PSK.replace('0', "g");
+        assert_eq!(
+            WifiCredentials::new("home", &not_hex),
+            Err(CredentialsError::PasswordLength)
+        );
+        assert_eq!(
+            WifiCredentials::new("home", &"a".repeat(65)),
+            Err(CredentialsError::PasswordLength)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ssids() {
+        assert_eq!(
+            WifiCredentials::new("", "12345678"),
+            Err(CredentialsError::SsidLength)
+        );
+        assert_eq!(
+            WifiCredentials::new(&"s".repeat(33), "12345678"),
+            Err(CredentialsError::SsidLength)
+        );
+        assert!(WifiCredentials::new(&"s".repeat(32), "12345678").is_ok());
+        assert_eq!(
+            WifiCredentials::from_bytes(&[0xff], b"12345678"),
+            Err(CredentialsError::NotUtf8)
+        );
+    }
+
+    #[test]
+    fn record_round_trip() {
+        for credentials in [
+            WifiCredentials::new("home", "correct horse").unwrap(),
+            WifiCredentials::new("cafe", "").unwrap(),
+            WifiCredentials::new(&"s".repeat(32), PSK).unwrap(),
+        ] {
+            let record = credentials.to_record();
+            assert_eq!(WifiCredentials::from_record(&record), Some(credentials));
+        }
+    }
+
+    #[test]
+    fn rejects_damaged_records() {
+        let record = WifiCredentials::new("home", "correct horse")
+            .unwrap()
+            .to_record();
+        for i in 0..RECORD_LEN {
+            let mut damaged = record;
+            damaged[i] ^= 0x01;
+            assert_eq!(WifiCredentials::from_record(&damaged), None, "byte {i}");
+        }
+        assert_eq!(
+            WifiCredentials::from_record(&record[..RECORD_LEN - 1]),
+            None
+        );
+        // erased flash
+        assert_eq!(WifiCredentials::from_record(&[0xff; RECORD_LEN]), None);
+    }
+}
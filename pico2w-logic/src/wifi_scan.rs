@@ -0,0 +1,291 @@
+use heapless::{String, Vec};
+
+// The networks found by a wifi scan (see `radio::scan`) and the search for the one closest to a misspelled ssid.
+// Access points answer a scan on every channel they hear it, so the same network shows up several times and only its
+// strongest answer is kept.
+
+pub const MAX_NETWORKS: usize = 20;
+pub const MAX_SSID_LEN: usize = 32;
+// the privacy bit of the capability field in the beacon
+const CAPABILITY_PRIVACY: u16 = 0x0010;
+
+// The scan results of the radio firmware end before the information elements that tell WEP, WPA, WPA2 and WPA3 apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Security {
+    Open,
+    Secured,
+}
+
+impl Security {
+    pub fn from_capability(capability: u16) -> Self {
+        if capability & CAPABILITY_PRIVACY == 0 {
+            Self::Open
+        } else {
+            Self::Secured
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Secured => "secured",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannedNetwork {
+    // empty for a hidden network, bytes that are not utf-8 are shown as '?'
+    pub ssid: String<MAX_SSID_LEN>,
+    pub bssid: [u8; 6],
+    pub channel: u8,
+    // dBm
+    pub rssi: i16,
+    pub security: Security,
+}
+
+impl ScannedNetwork {
+    pub fn new(ssid: &[u8], bssid: [u8; 6], channel: u8, rssi: i16, capability: u16) -> Self {
+        let ssid = &ssid[..ssid.len().min(MAX_SSID_LEN)];
+        let ssid = match core::str::from_utf8(ssid) {
+            Ok(ssid) => String::try_from(ssid).unwrap(),
+            Err(_) => ssid
+                .iter()
+                .map(|b| if b.is_ascii() { *b as char } else { '?' })
+                .collect(),
+        };
+
+        Self {
+            ssid,
+            bssid,
+            channel,
+            rssi,
+            security: Security::from_capability(capability),
+        }
+    }
+}
+
+// the networks heard, strongest first
+#[derive(Debug, Clone, Default)]
+pub struct ScanResults {
+    networks: Vec<ScannedNetwork, MAX_NETWORKS>,
+}
+
+impl ScanResults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Keeps the strongest answer of every access point and, once full, the `MAX_NETWORKS` strongest access points
+    pub fn add(&mut self, network: ScannedNetwork) {
+        if let Some(index) = self
+            .networks
+            .iter()
+            .position(|known| known.bssid == network.bssid)
+        {
+            if network.rssi <= self.networks[index].rssi {
+                return;
+            }
+            self.networks.remove(index);
+        }
+
+        let index = self
+            .networks
+            .iter()
+            .position(|known| network.rssi > known.rssi)
+            .unwrap_or(self.networks.len());
+        if index == MAX_NETWORKS {
+            return;
+        }
+        if self.networks.is_full() {
+            self.networks.pop();
+        }
+        let _ = self.networks.insert(index, network);
+    }
+
+    pub fn networks(&self) -> &[ScannedNetwork] {
+        &self.networks
+    }
+
+    // the strongest access point of `ssid`
+    pub fn find(&self, ssid: &str) -> Option<&ScannedNetwork> {
+        self.networks.iter().find(|network| network.ssid == ssid)
+    }
+
+    // The network whose ssid needs the fewest single character edits (ignoring case) to become `ssid`, together with
+    // that number. The stronger network wins a tie and hidden networks are skipped.
+    pub fn closest(&self, ssid: &str) -> Option<(&ScannedNetwork, usize)> {
+        self.networks
+            .iter()
+            .filter(|network| !network.ssid.is_empty())
+            .map(|network| (network, edit_distance(&network.ssid, ssid)))
+            .min_by_key(|(_, distance)| *distance)
+    }
+}
+
+// Levenshtein distance of the first `MAX_SSID_LEN` bytes, ascii letters compared without case
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a = &a.as_bytes()[..a.len().min(MAX_SSID_LEN)];
+    let b = &b.as_bytes()[..b.len().min(MAX_SSID_LEN)];
+
+    // the distances from the first `i` bytes of `a` to every prefix of `b`
+    let mut previous = [0; MAX_SSID_LEN + 1];
+    let mut current = [0; MAX_SSID_LEN + 1];
+    for (j, distance) in previous.iter_mut().enumerate().take(b.len() + 1) {
+        *distance = j;
+    }
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(!ca.eq_ignore_ascii_case(cb));
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ssid: &str, last_bssid_byte: u8, rssi: i16) -> ScannedNetwork {
+        ScannedNetwork::new(
+            ssid.as_bytes(),
+            [0x28, 0xcd, 0xc1, 0, 0, last_bssid_byte],
+            6,
+            rssi,
+            CAPABILITY_PRIVACY,
+        )
+    }
+
+    fn ssids(results: &ScanResults) -> std::vec::Vec<&str> {
+        results
+            .networks()
+            .iter()
+            .map(|network| network.ssid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn creates_networks() {
+        let network = ScannedNetwork::new(b"home", [1, 2, 3, 4, 5, 6], 11, -48, 0x0411);
+        assert_eq!(network.ssid, "home");
+        assert_eq!(network.channel, 11);
+        assert_eq!(network.security, Security::Secured);
+        assert_eq!(
+            ScannedNetwork::new(b"cafe", [0; 6], 1, -70, 0x0401).security,
+            Security::Open
+        );
+
+        // bytes that are not utf-8 and ssids longer than allowed
+        assert_eq!(
+            ScannedNetwork::new(b"caf\xe9", [0; 6], 1, -70, 0).ssid,
+            "caf?"
+        );
+        assert_eq!(
+            ScannedNetwork::new(&[b'x'; 40], [0; 6], 1, -70, 0)
+                .ssid
+                .len(),
+            MAX_SSID_LEN
+        );
+    }
+
+    #[test]
+    fn keeps_strongest_answer_of_access_point() {
+        let mut results = ScanResults::new();
+        results.add(network("home", 1, -70));
+        results.add(network("home", 1, -50));
+        results.add(network("home", 1, -60));
+        assert_eq!(results.networks().len(), 1);
+        assert_eq!(results.networks()[0].rssi, -50);
+    }
+
+    #[test]
+    fn keeps_every_access_point_of_an_ssid() {
+        // e.g. a mesh network, `find` picks the strongest
+        let mut results = ScanResults::new();
+        results.add(network("home", 1, -70));
+        results.add(network("office", 2, -65));
+        results.add(network("home", 3, -55));
+        assert_eq!(ssids(&results), ["home", "office", "home"]);
+        assert_eq!(results.find("home").unwrap().bssid[5], 3);
+        assert!(results.find("Home").is_none());
+    }
+
+    #[test]
+    fn sorts_strongest_first() {
+        let mut results = ScanResults::new();
+        results.add(network("b", 1, -60));
+        results.add(network("c", 2, -80));
+        results.add(network("a", 3, -40));
+        // a stronger answer of an access point moves it up
+        results.add(network("c", 2, -50));
+        assert_eq!(ssids(&results), ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn keeps_strongest_networks_when_full() {
+        let mut results = ScanResults::new();
+        for i in 0..MAX_NETWORKS as u8 {
+            results.add(network("net", i, -50 - i16::from(i)));
+        }
+        assert_eq!(results.networks().len(), MAX_NETWORKS);
+
+        // weaker than all of them
+        results.add(network("weak", 100, -90));
+        assert!(results.find("weak").is_none());
+
+        // replaces the weakest
+        results.add(network("strong", 101, -30));
+        assert_eq!(results.networks().len(), MAX_NETWORKS);
+        assert_eq!(results.networks()[0].ssid, "strong");
+        let weakest = results.networks().last().unwrap();
+        assert_eq!(weakest.rssi, -50 - (MAX_NETWORKS as i16 - 2));
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("home", "home"), 0);
+        assert_eq!(edit_distance("", "home"), 4);
+        assert_eq!(edit_distance("home", ""), 4);
+        assert_eq!(edit_distance("hmoe", "home"), 2);
+        assert_eq!(edit_distance("hom", "home"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        // case is only ignored for ascii letters, and bytes are compared rather than characters
+        assert_eq!(edit_distance("MyNetwork", "mynetwork"), 0);
+        assert_eq!(edit_distance("caf\u{c9}", "caf\u{e9}"), 1);
+        // only the first `MAX_SSID_LEN` bytes count
+        let long = "x".repeat(MAX_SSID_LEN);
+        assert_eq!(edit_distance(&(long.clone() + "abc"), &long), 0);
+    }
+
+    #[test]
+    fn finds_closest_ssid() {
+        let mut results = ScanResults::new();
+        results.add(network("MyNetwork-5G", 1, -45));
+        results.add(network("Neighbour", 2, -60));
+        results.add(network("", 3, -30));
+
+        // a different case or a band suffix
+        let (closest, distance) = results.closest("mynetwork").unwrap();
+        assert_eq!((closest.ssid.as_str(), distance), ("MyNetwork-5G", 3));
+        let (closest, distance) = results.closest("MyNetwork-5g").unwrap();
+        assert_eq!((closest.ssid.as_str(), distance), ("MyNetwork-5G", 0));
+        // a typo, the hidden network is never suggested
+        let (closest, distance) = results.closest("Neighbor").unwrap();
+        assert_eq!((closest.ssid.as_str(), distance), ("Neighbour", 1));
+    }
+
+    #[test]
+    fn closest_prefers_stronger_network_on_tie() {
+        let mut results = ScanResults::new();
+        results.add(network("home1", 1, -70));
+        results.add(network("home2", 2, -50));
+        assert_eq!(results.closest("home").unwrap().0.ssid, "home2");
+
+        assert!(ScanResults::new().closest("home").is_none());
+    }
+}
@@ -1,7 +1,7 @@
 #!/bin/bash
 set -e
 
-~/source/picotool/build/picotool load -u -v -x -t elf $1
+~/source/picotool/build/picotool load -u -v -x -f -t elf $1
 
 USB_SERIAL="/dev/ttyACM0"
 
@@ -0,0 +1,28 @@
+#!/bin/bash
+set -e
+
+# Compares the size of every example built normally and with the `no-logging` feature.
+# Needs llvm-size (or set SIZE to another berkeley style size tool, e.g. SIZE=arm-none-eabi-size)
+SIZE=${SIZE:-llvm-size}
+TARGET_DIR=target/thumbv8m.main-none-eabihf/release
+BINS=$(ls src/bin | sed 's/\.rs$//')
+
+# prints "<flash> <ram>" in bytes, flash is code and initialised data, ram is initialised and zeroed data
+measure() {
+    $SIZE "$TARGET_DIR/$1" | awk 'NR == 2 { print $1 + $2, $2 + $3 }'
+}
+
+declare -A BEFORE
+cargo build --release --bins --quiet
+for bin in $BINS; do
+    BEFORE[$bin]=$(measure "$bin")
+done
+
+cargo build --release --bins --quiet --features no-logging
+
+printf "%-12s %10s %10s %10s %10s %10s %10s\n" "example" "flash" "no-logging" "saved" "ram" "no-logging" "saved"
+for bin in $BINS; do
+    read -r flash ram <<< "${BEFORE[$bin]}"
+    read -r small_flash small_ram <<< "$(measure "$bin")"
+    printf "%-12s %10d %10d %10d %10d %10d %10d\n" "$bin" "$flash" "$small_flash" $((flash - small_flash)) "$ram" "$small_ram" $((ram - small_ram))
+done
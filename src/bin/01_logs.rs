@@ -1,6 +1,6 @@
 //! Start here. This example tests the RP Pico2 W USB serial port logging.
 //! This demo starts up and launches a logging task to capture logging messages and to send them over the USB serial port.
-//! It then waits for the host (the pc connected to the rp) to connect and start listening to text coming from the serial port
+//! It then waits (up to 10 seconds) for the host (the pc connected to the rp) to open the serial port and start listening to it
 //! Then it logs a counter every second to show you that it is working. If you do not see any log messages after the program
 //! has finished downloading then there is a communication problem.
 //!
@@ -15,10 +15,16 @@
 //! `Error: "Unable to find mounted pico"`
 //! This is because the pico is not in bootloader mode. You need to press down the BOOTSEL button when you plug it in and then release the button.
 //! You need to do this every time you download firmware onto the device.
+//!
+//! Serial shell:
+//! Type `help` into the serial monitor and press enter to list the commands the board understands.
+//! This example adds its own `count` command (see `COUNT_COMMAND` below) which shows or resets the counter.
 
 #![no_std]
 #![no_main]
 
+use core::fmt::Write;
+
 use embassy_executor::Spawner;
 use embassy_rp::{
     bind_interrupts,
@@ -26,13 +32,36 @@ use embassy_rp::{
     usb::{self},
 };
 use embassy_time::{Duration, Timer};
-use log::info;
-use rp_pico2w_examples::{self as _, logging::setup_logging};
+use portable_atomic::{AtomicU32, Ordering};
+use rp_pico2w_examples::{
+    self as _, info,
+    logging::{setup_logging, wait_for_host},
+    shell::{self, Args, Command},
+};
 
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => usb::InterruptHandler<USB>;
 });
 
+static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+static COUNT_COMMAND: Command = Command {
+    name: "count",
+    help: "show the counter or reset it with 'count reset'",
+    run: count,
+};
+
+fn count(mut args: Args<'_>, out: &mut dyn Write) -> Result<(), &'static str> {
+    match args.next() {
+        None => {}
+        Some("reset") => COUNTER.store(0, Ordering::Relaxed),
+        Some(_) => return Err("usage: count [reset]"),
+    }
+
+    let _ = writeln!(out, "count: {}", COUNTER.load(Ordering::Relaxed));
+    Ok(())
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     let p = embassy_rp::init(Default::default());
@@ -40,16 +69,18 @@ async fn main(spawner: Spawner) {
     // setup logging over usb serial port
     let driver = usb::Driver::new(p.USB, Irqs);
     setup_logging(&spawner, driver);
+    shell::register(&COUNT_COMMAND);
 
-    // wait for host to connect to usb serial port
-    Timer::after(Duration::from_secs(1)).await;
+    // log messages are kept until the host opens the usb serial port, waiting for it here
+    // just means the counter starts at 0 when you are watching
+    if !wait_for_host(Duration::from_secs(10)).await {
+        info!("no serial monitor connected, counting anyway");
+    }
     info!("started");
     info!("press Ctrl C to quit");
 
-    let mut counter = 0;
     loop {
-        info!("count: {}", counter);
-        counter += 1;
+        info!("count: {}", COUNTER.fetch_add(1, Ordering::Relaxed));
         Timer::after(Duration::from_secs(1)).await;
     }
 }
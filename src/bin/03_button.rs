@@ -17,18 +17,15 @@
 #![no_std]
 #![no_main]
 
-use cyw43_pio::{PioSpi, RM2_CLOCK_DIVIDER};
 use embassy_executor::Spawner;
 use embassy_rp::{
     bind_interrupts,
-    gpio::{Input, Level, Output, Pull},
+    gpio::{Input, Pull},
     peripherals::{PIO0, USB},
-    pio::{self, Pio},
-    usb::{self},
+    pio, usb,
 };
 use embassy_time::{Duration, Timer};
-use log::info;
-use rp_pico2w_examples::{self as _, logging::setup_logging, radio::setup_radio};
+use rp_pico2w_examples::{self as _, board::PicoW2Board, info};
 
 bind_interrupts!(struct Irqs {
     USBCTRL_IRQ => usb::InterruptHandler<USB>;
@@ -37,41 +34,20 @@ bind_interrupts!(struct Irqs {
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
-    let p = embassy_rp::init(Default::default());
+    let board = PicoW2Board::init(&spawner, embassy_rp::init(Default::default()), Irqs).await;
+    let control = board.control;
 
-    // setup logging over usb serial port
-    let driver = usb::Driver::new(p.USB, Irqs);
-    setup_logging(&spawner, driver);
-
-    // wait for host to connect to usb serial port
-    Timer::after(Duration::from_millis(1000)).await;
     info!("started");
 
-    // setup spi bus for wifi modem
-    let pwr = Output::new(p.PIN_23, Level::Low);
-    let cs = Output::new(p.PIN_25, Level::High);
-    let mut pio = Pio::new(p.PIO0, Irqs);
-    let spi = PioSpi::new(
-        &mut pio.common,
-        pio.sm0,
-        RM2_CLOCK_DIVIDER,
-        pio.irq0,
-        cs,
-        p.PIN_24,
-        p.PIN_29,
-        p.DMA_CH0,
-    );
-    let (_net_device, mut control) = setup_radio(&spawner, pwr, spi).await;
-
     // this is GP14 (not the physical chip pin number!)
-    let mut button = Input::new(p.PIN_14, Pull::Up);
+    let mut button = Input::new(board.p.PIN_14, Pull::Up);
 
     loop {
         info!("waiting for button press");
         button.wait_for_low().await;
 
         info!("led on!");
-        control.gpio_set(0, true).await;
+        control.lock().await.gpio_set(0, true).await;
 
         // debounce the button
         Timer::after(Duration::from_millis(250)).await;
@@ -80,7 +56,7 @@ async fn main(spawner: Spawner) {
         button.wait_for_high().await;
 
         info!("led off!");
-        control.gpio_set(0, false).await;
+        control.lock().await.gpio_set(0, false).await;
 
         // debounce the button
         Timer::after(Duration::from_millis(250)).await;
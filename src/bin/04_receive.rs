@@ -2,6 +2,11 @@
 //! In order to connect to the wifi network please create the following two files in the `src` folder:
 //! WIFI_SSID.txt and WIFI_PASSWORD.txt
 //! The files above should contain the exact ssid and password to connect to the wifi network. No newline characters or quotes.
+//! OPTIONAL: put the ip address of a syslog collector in SYSLOG_IP.txt to also send log messages over the network
+//! (e.g. run `nc -ul 514` on that machine).
+//! OPTIONAL: set PICO_AP_SSID in `.cargo/config.toml` to host a network for `05_send` instead of joining one, see
+//! "Access point mode" in the readme.
+//! The led reacts faster after typing `wifi-power performance` in the serial shell, see "Wi-Fi power mode" in the readme.
 //!
 //! NOTE: This targets a RP Pico2 W or PR Pico2 WH. It does not work with the RP Pico2 board (non-wifi).
 //!
@@ -21,20 +26,20 @@
 
 use core::str::{from_utf8, FromStr};
 
-use cyw43_pio::{PioSpi, RM2_CLOCK_DIVIDER};
 use embassy_executor::Spawner;
-use embassy_net::Ipv4Address;
+use embassy_net::{IpEndpoint, Ipv4Address};
 use embassy_rp::{
     bind_interrupts,
-    gpio::{Level, Output},
     peripherals::{PIO0, USB},
-    pio::{self, Pio},
-    usb::{self},
+    pio, usb,
 };
-use embassy_time::{Duration, Timer};
-use log::{error, info, warn};
 use rp_pico2w_examples::{
-    self as _, logging::setup_logging, network::setup_network, radio::setup_radio,
+    self as _,
+    board::PicoW2Board,
+    error, info,
+    logging::syslog::{setup_syslog, SYSLOG_PORT},
+    network::{access_point, setup_access_point, setup_network},
+    warn,
 };
 
 bind_interrupts!(struct Irqs {
@@ -46,35 +51,32 @@ bind_interrupts!(struct Irqs {
 async fn main(spawner: Spawner) {
     const LOCAL_PORT: u16 = 47900;
     let local_ip = Ipv4Address::from_str(include_str!("../LOCAL_IP.txt")).ok();
+    let syslog_ip = Ipv4Address::from_str(include_str!("../SYSLOG_IP.txt")).ok();
 
-    let p = embassy_rp::init(Default::default());
+    // before the board is set up so that the radio bring-up and join messages are queued for the collector too
+    if let Some(syslog_ip) = syslog_ip {
+        setup_syslog(&spawner, IpEndpoint::new(syslog_ip.into(), SYSLOG_PORT));
+    }
 
-    // setup logging over usb serial port
-    let driver = usb::Driver::new(p.USB, Irqs);
-    setup_logging(&spawner, driver);
+    let board = PicoW2Board::init(&spawner, embassy_rp::init(Default::default()), Irqs).await;
+    let control = board.control;
 
-    // wait for host to connect to usb serial port
-    Timer::after(Duration::from_millis(1000)).await;
     info!("started");
 
-    // setup spi bus for wifi modem
-    let pwr = Output::new(p.PIN_23, Level::Low);
-    let cs = Output::new(p.PIN_25, Level::High);
-    let mut pio = Pio::new(p.PIO0, Irqs);
-    let spi = PioSpi::new(
-        &mut pio.common,
-        pio.sm0,
-        RM2_CLOCK_DIVIDER,
-        pio.irq0,
-        cs,
-        p.PIN_24,
-        p.PIN_29,
-        p.DMA_CH0,
-    );
-    let (net_device, mut control) = setup_radio(&spawner, pwr, spi).await;
-
-    let socket = setup_network(&spawner, net_device, &mut control, local_ip, LOCAL_PORT).await;
-    info!("waiting for udp packets on port {LOCAL_PORT}");
+    let socket = match access_point() {
+        Some(access_point) => {
+            setup_access_point(
+                &spawner,
+                board.net_device,
+                control,
+                &access_point,
+                LOCAL_PORT,
+            )
+            .await
+        }
+        None => setup_network(&spawner, board.net_device, control, local_ip, LOCAL_PORT).await,
+    };
+    info!("waiting for udp packets on port {}", LOCAL_PORT);
 
     let mut buf: [u8; 32] = [0; 32];
     loop {
@@ -83,12 +85,17 @@ async fn main(spawner: Spawner) {
                 Ok(s) => {
                     info!("received '{}' from {:?}", s, meta);
                     match s {
-                        "on" => control.gpio_set(0, true).await,
-                        "off" => control.gpio_set(0, false).await,
+                        "on" => control.lock().await.gpio_set(0, true).await,
+                        "off" => control.lock().await.gpio_set(0, false).await,
                         _ => warn!("unknown command received"),
                     }
                 }
-                Err(e) => warn!("received {} bytes from {:?}: {:?}", len, meta, e),
+                Err(e) => warn!(
+                    "received {} bytes from {:?}: invalid utf-8 at byte {}",
+                    len,
+                    meta,
+                    e.valid_up_to()
+                ),
             },
             Err(e) => error!("error receiving packet: {:?}", e),
         }
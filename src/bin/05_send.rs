@@ -2,6 +2,10 @@
 //! In order to connect to the wifi network please create the following two files in the `src` folder:
 //! WIFI_SSID.txt and WIFI_PASSWORD.txt
 //! The files above should contain the exact ssid and password to connect to the wifi network. No newline characters or quotes.
+//! OPTIONAL: put the ip address of a syslog collector in SYSLOG_IP.txt to also send log messages over the network
+//! (e.g. run `nc -ul 514` on that machine).
+//! OPTIONAL: set PICO_AP_SSID in `.cargo/config.toml` to join the network hosted by `04_receive` instead, then REMOTE_IP.txt
+//! and LOCAL_IP.txt are not used. See "Access point mode" in the readme.
 //!
 //! NOTE: This targets a RP Pico2 W or PR Pico2 WH. It does not work with the RP Pico2 board (non-wifi).
 //!
@@ -21,21 +25,22 @@
 
 use core::str::FromStr;
 
-use cyw43::Control;
-use cyw43_pio::{PioSpi, RM2_CLOCK_DIVIDER};
 use embassy_executor::Spawner;
 use embassy_net::{udp::UdpSocket, IpEndpoint, Ipv4Address};
 use embassy_rp::{
     bind_interrupts,
-    gpio::{Input, Level, Output, Pull},
+    gpio::{Input, Pull},
     peripherals::{PIO0, USB},
-    pio::{self, Pio},
-    usb::{self},
+    pio, usb,
 };
 use embassy_time::{Duration, Timer};
-use log::{error, info};
 use rp_pico2w_examples::{
-    self as _, logging::setup_logging, network::setup_network, radio::setup_radio,
+    self as _,
+    board::PicoW2Board,
+    error, info,
+    logging::syslog::{setup_syslog, SYSLOG_PORT},
+    network::{access_point, setup_network, setup_network_with, AP_ADDRESS},
+    radio::SharedControl,
 };
 
 bind_interrupts!(struct Irqs {
@@ -50,37 +55,41 @@ async fn main(spawner: Spawner) {
     let remote_ip =
         Ipv4Address::from_str(include_str!("../REMOTE_IP.txt")).expect("invalid remote ip address");
     let local_ip = Ipv4Address::from_str(include_str!("../LOCAL_IP.txt")).ok();
+    let syslog_ip = Ipv4Address::from_str(include_str!("../SYSLOG_IP.txt")).ok();
 
-    let p = embassy_rp::init(Default::default());
+    // before the board is set up so that the radio bring-up and join messages are queued for the collector too
+    if let Some(syslog_ip) = syslog_ip {
+        setup_syslog(&spawner, IpEndpoint::new(syslog_ip.into(), SYSLOG_PORT));
+    }
 
-    // setup logging over usb serial port
-    let driver = usb::Driver::new(p.USB, Irqs);
-    setup_logging(&spawner, driver);
+    let board = PicoW2Board::init(&spawner, embassy_rp::init(Default::default()), Irqs).await;
+    let control = board.control;
 
-    // wait for host to connect to usb serial port
-    Timer::after(Duration::from_millis(1000)).await;
     info!("started");
 
-    // setup spi bus for wifi modem
-    let pwr = Output::new(p.PIN_23, Level::Low);
-    let cs = Output::new(p.PIN_25, Level::High);
-    let mut pio = Pio::new(p.PIO0, Irqs);
-    let spi = PioSpi::new(
-        &mut pio.common,
-        pio.sm0,
-        RM2_CLOCK_DIVIDER,
-        pio.irq0,
-        cs,
-        p.PIN_24,
-        p.PIN_29,
-        p.DMA_CH0,
-    );
-    let (net_device, mut control) = setup_radio(&spawner, pwr, spi).await;
-
-    let socket = setup_network(&spawner, net_device, &mut control, local_ip, LOCAL_PORT).await;
+    // the board running `04_receive` hosts the network and hands out addresses
+    let (socket, remote_ip) = match access_point() {
+        Some(access_point) => {
+            let socket = setup_network_with(
+                &spawner,
+                board.net_device,
+                control,
+                &access_point.credentials,
+                None,
+                LOCAL_PORT,
+            )
+            .await;
+            (socket, AP_ADDRESS)
+        }
+        None => {
+            let socket =
+                setup_network(&spawner, board.net_device, control, local_ip, LOCAL_PORT).await;
+            (socket, remote_ip)
+        }
+    };
 
     // this is GP14 (not the physical chip pin number!)
-    let mut button = Input::new(p.PIN_14, Pull::Up);
+    let mut button = Input::new(board.p.PIN_14, Pull::Up);
 
     let remote_endpoint = IpEndpoint::new(remote_ip.into(), REMOTE_PORT);
     let mut on = false;
@@ -95,7 +104,7 @@ async fn main(spawner: Spawner) {
         Timer::after(Duration::from_millis(250)).await;
 
         on = button.is_low();
-        send(on, &socket, remote_endpoint, &mut control).await;
+        send(on, &socket, remote_endpoint, control).await;
     }
 }
 
@@ -103,14 +112,14 @@ async fn send(
     on: bool,
     socket: &UdpSocket<'static>,
     remote_endpoint: IpEndpoint,
-    control: &mut Control<'static>,
+    control: &SharedControl,
 ) {
     info!(
         "send led {} to {:?}",
         if on { "on" } else { "off" },
         remote_endpoint
     );
-    control.gpio_set(0, on).await;
+    control.lock().await.gpio_set(0, on).await;
 
     match socket
         .send_to(if on { b"on" } else { b"off" }, remote_endpoint)
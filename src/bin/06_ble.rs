@@ -0,0 +1,154 @@
+//! This example exposes the on board LED and the button on GP14 over Bluetooth LE. See button example first.
+//! The board advertises as `pico2w-led` with a GATT service (see `led_service.rs` for the uuids) that has two characteristics:
+//! led: read / write a single byte, 1 turns the led on and 0 turns it off
+//! button: read / notify a single byte, 1 while GP14 is connected to GND
+//! Any BLE app that can browse GATT services works as the client, e.g. nRF Connect on a phone.
+//!
+//! NOTE: This targets a RP Pico2 W or PR Pico2 WH. It does not work with the RP Pico2 board (non-wifi).
+//!
+//! How to run with a standard usb cable (no debug probe):
+//! The pico has a builtin bootloader that can be used as a replacement for a debug probe (like an ST link v2).
+//! Start with the usb cable unplugged then, while holding down the BOOTSEL button, plug it in. Then you can release the button.
+//! Mount the usb drive (this will be enumerated as USB mass storage) then run the following command:
+//! cargo run --bin 06_ble --release
+//!
+//! Troubleshoot:
+//! `Error: "Unable to find mounted pico"`
+//! This is because the pico is not in bootloader mode. You need to press down the BOOTSEL button when you plug it in and then release the button.
+//! You need to do this every time you download firmware onto the device.
+
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use embassy_futures::select::select;
+use embassy_rp::{
+    bind_interrupts,
+    gpio::{Input, Pull},
+    peripherals::{PIO0, USB},
+    pio, usb,
+};
+use embassy_time::{Duration, Timer};
+use rp_pico2w_examples::{
+    self as _,
+    ble::{advertise, setup_ble},
+    board::PicoW2Board,
+    error, info,
+    led_service::{LedServer, LED_SERVICE_UUID},
+    radio::SharedControl,
+    warn,
+};
+use trouble_host::prelude::{DefaultPacketPool, GattConnection, GattConnectionEvent, GattEvent};
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => usb::InterruptHandler<USB>;
+    PIO0_IRQ_0 => pio::InterruptHandler<PIO0>;
+});
+
+const NAME: &str = "pico2w-led";
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    let (board, bt_device) =
+        PicoW2Board::init_with_bluetooth(&spawner, embassy_rp::init(Default::default()), Irqs)
+            .await;
+    let control = board.control;
+
+    // this is GP14 (not the physical chip pin number!)
+    let mut button = Input::new(board.p.PIN_14, Pull::Up);
+
+    let mut peripheral = setup_ble(&spawner, bt_device, control).await;
+    let server = LedServer::with_name(NAME).unwrap();
+
+    loop {
+        let conn = match advertise(&mut peripheral, NAME, LED_SERVICE_UUID).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("advertising failed: {:?}", e);
+                Timer::after(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let conn = match conn.with_attribute_server(&server) {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("gatt connection failed: {:?}", e);
+                continue;
+            }
+        };
+
+        info!("connected");
+        server.set_button(button.is_low()).ok();
+        select(
+            handle_events(&server, &conn, control),
+            report_button(&server, &conn, &mut button),
+        )
+        .await;
+    }
+}
+
+// returns when the central disconnects
+async fn handle_events(
+    server: &LedServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    control: &SharedControl,
+) {
+    loop {
+        match conn.next().await {
+            GattConnectionEvent::Disconnected { reason } => {
+                info!("disconnected: {:?}", reason);
+                return;
+            }
+            GattConnectionEvent::Gatt { event } => {
+                let result = match &event {
+                    GattEvent::Write(write) => server.check_write(write.handle(), write.data()),
+                    _ => Ok(None),
+                };
+
+                let reply = match result {
+                    Ok(led) => {
+                        if let Some(on) = led {
+                            info!("led {}", if on { "on" } else { "off" });
+                            control.lock().await.gpio_set(0, on).await;
+                        }
+                        event.accept()
+                    }
+                    Err(e) => {
+                        warn!("rejected write: {:?}", e);
+                        event.reject(e)
+                    }
+                };
+
+                match reply {
+                    Ok(reply) => reply.send().await,
+                    Err(e) => error!("gatt reply error: {:?}", e),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn report_button(
+    server: &LedServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    button: &mut Input<'_>,
+) {
+    let mut pressed = button.is_low();
+    loop {
+        button.wait_for_any_edge().await;
+
+        // debounce the button
+        Timer::after(Duration::from_millis(50)).await;
+        if button.is_low() == pressed {
+            continue;
+        }
+
+        pressed = !pressed;
+        info!("button {}", if pressed { "pressed" } else { "released" });
+        if let Err(e) = server.notify_button(conn, pressed).await {
+            error!("button notify error: {:?}", e);
+        }
+    }
+}
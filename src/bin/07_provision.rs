@@ -0,0 +1,77 @@
+//! This example receives its wifi credentials from a phone over Bluetooth LE instead of WIFI_SSID.txt and WIFI_PASSWORD.txt.
+//! Without saved credentials (or while GP14 is connected to GND when it starts) the board advertises as `pico2w-setup` with a
+//! GATT service (see `provisioning_service.rs` for the uuids). Write the ssid and the password to their characteristics, then
+//! write 1 to the connect characteristic. The status characteristic notifies the outcome (see `Status`). Once the board has
+//! joined the network the credentials are saved to flash and used on every boot, also by the other examples.
+//! Then it echoes udp packets sent to port 47900 back to the sender.
+//!
+//! NOTE: This targets a RP Pico2 W or PR Pico2 WH. It does not work with the RP Pico2 board (non-wifi).
+//!
+//! How to run with a standard usb cable (no debug probe):
+//! The pico has a builtin bootloader that can be used as a replacement for a debug probe (like an ST link v2).
+//! Start with the usb cable unplugged then, while holding down the BOOTSEL button, plug it in. Then you can release the button.
+//! Mount the usb drive (this will be enumerated as USB mass storage) then run the following command:
+//! cargo run --bin 07_provision --release
+//!
+//! Troubleshoot:
+//! `Error: "Unable to find mounted pico"`
+//! This is because the pico is not in bootloader mode. You need to press down the BOOTSEL button when you plug it in and then release the button.
+//! You need to do this every time you download firmware onto the device.
+
+#![no_std]
+#![no_main]
+
+use embassy_executor::Spawner;
+use embassy_rp::{
+    bind_interrupts,
+    gpio::{Input, Pull},
+    peripherals::{PIO0, USB},
+    pio, usb,
+};
+use rp_pico2w_examples::{
+    self as _,
+    ble::setup_ble,
+    board::PicoW2Board,
+    error, info,
+    network::setup_network,
+    provisioning::{provision, saved_credentials, BoardFlash},
+};
+
+bind_interrupts!(struct Irqs {
+    USBCTRL_IRQ => usb::InterruptHandler<USB>;
+    PIO0_IRQ_0 => pio::InterruptHandler<PIO0>;
+});
+
+#[embassy_executor::main]
+async fn main(spawner: Spawner) {
+    const LOCAL_PORT: u16 = 47900;
+
+    let (board, bt_device) =
+        PicoW2Board::init_with_bluetooth(&spawner, embassy_rp::init(Default::default()), Irqs)
+            .await;
+    let control = board.control;
+
+    // this is GP14 (not the physical chip pin number!)
+    let button = Input::new(board.p.PIN_14, Pull::Up);
+    let mut flash = BoardFlash::new_blocking(board.p.FLASH);
+
+    if saved_credentials().is_none() || button.is_low() {
+        let mut peripheral = setup_ble(&spawner, bt_device, control).await;
+        provision(&mut peripheral, control, &mut flash).await;
+    }
+
+    let socket = setup_network(&spawner, board.net_device, control, None, LOCAL_PORT).await;
+    info!("echoing udp packets on port {}", LOCAL_PORT);
+
+    let mut buf = [0; 512];
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((len, meta)) => {
+                if let Err(e) = socket.send_to(&buf[..len], meta.endpoint).await {
+                    error!("error sending packet: {:?}", e);
+                }
+            }
+            Err(e) => error!("error receiving packet: {:?}", e),
+        }
+    }
+}
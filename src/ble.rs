@@ -0,0 +1,103 @@
+use cyw43::bluetooth::{self, BtDriver};
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use static_cell::StaticCell;
+use trouble_host::prelude::*;
+
+use crate::{fmt::HexBytes, radio::SharedControl};
+
+// The BLE host stack (trouble-host) on top of the bluetooth controller in the radio, which it talks to with HCI over
+// the same spi bus as wifi. See `PicoW2Board::init_with_bluetooth` and the `06_ble` example.
+
+pub type BleController = ExternalController<BtDriver<'static>, 10>;
+pub type BleError = BleHostError<bluetooth::Error>;
+pub type BlePeripheral = Peripheral<'static, BleController, DefaultPacketPool>;
+pub type BleConnection = Connection<'static, DefaultPacketPool>;
+
+type BleStack = Stack<'static, BleController, DefaultPacketPool>;
+
+// one central at a time, with the L2CAP signalling and attribute protocol channels
+const CONNECTIONS_MAX: usize = 1;
+const L2CAP_CHANNELS_MAX: usize = 2;
+
+type Resources = HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX>;
+
+// Starts the host stack and returns the peripheral role used to advertise and accept connections
+pub async fn setup_ble(
+    spawner: &Spawner,
+    bt_device: BtDriver<'static>,
+    control: &SharedControl,
+) -> BlePeripheral {
+    let address = static_random_address(control.lock().await.address().await);
+
+    static RESOURCES: StaticCell<Resources> = StaticCell::new();
+    static STACK: StaticCell<BleStack> = StaticCell::new();
+    let resources = RESOURCES.init(Resources::new());
+    let stack = STACK.init(
+        trouble_host::new(ExternalController::new(bt_device), resources)
+            .set_random_address(Address::random(address)),
+    );
+
+    let Host {
+        peripheral, runner, ..
+    } = stack.build();
+    spawner.spawn(ble_task(runner)).unwrap();
+
+    info!("bluetooth setup complete, address {}", HexBytes(&address));
+    peripheral
+}
+
+#[embassy_executor::task]
+async fn ble_task(mut runner: Runner<'static, BleController, DefaultPacketPool>) -> ! {
+    loop {
+        if let Err(e) = runner.run().await {
+            error!("ble host error: {:?}", e);
+        }
+        Timer::after(Duration::from_secs(1)).await;
+    }
+}
+
+// Advertises `name` and a 128-bit service uuid until a central connects
+pub async fn advertise(
+    peripheral: &mut BlePeripheral,
+    name: &str,
+    service: u128,
+) -> Result<BleConnection, BleError> {
+    let mut adv_data = [0; 31];
+    let adv_len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::ServiceUuids128(&[service.to_le_bytes()]),
+        ],
+        &mut adv_data,
+    )?;
+
+    // the name goes into the scan response because it does not fit next to the service uuid
+    let mut scan_data = [0; 31];
+    let scan_len = AdStructure::encode_slice(
+        &[AdStructure::CompleteLocalName(name.as_bytes())],
+        &mut scan_data,
+    )?;
+
+    let advertiser = peripheral
+        .advertise(
+            &Default::default(),
+            Advertisement::ConnectableScannableUndirected {
+                adv_data: &adv_data[..adv_len],
+                scan_data: &scan_data[..scan_len],
+            },
+        )
+        .await?;
+
+    info!("advertising as '{}'", name);
+    Ok(advertiser.accept().await?)
+}
+
+// A static random address needs the two most significant bits set, the rest comes from the wifi mac address so that
+// every board keeps the same address across reboots. The address is little endian.
+fn static_random_address(mac: [u8; 6]) -> [u8; 6] {
+    let mut address = mac;
+    address.reverse();
+    address[5] |= 0xc0;
+    address
+}
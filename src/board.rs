@@ -0,0 +1,233 @@
+use cyw43::{bluetooth::BtDriver, NetDriver};
+// the RM2 radio module on the Pico 2 W needs a slower spi clock than the Pico W
+use cyw43_pio::PioSpi;
+#[cfg(feature = "rp2040")]
+use cyw43_pio::DEFAULT_CLOCK_DIVIDER as CLOCK_DIVIDER;
+#[cfg(feature = "rp235x")]
+use cyw43_pio::RM2_CLOCK_DIVIDER as CLOCK_DIVIDER;
+use embassy_executor::Spawner;
+use embassy_rp::{
+    gpio::{Level, Output},
+    interrupt::typelevel::{Binding, PIO0_IRQ_0, USBCTRL_IRQ},
+    peripherals::{self, DMA_CH0, PIO0, USB},
+    pio::{self, Pio},
+    usb, Peri,
+};
+
+use crate::{
+    logging::setup_logging,
+    radio::{
+        default_power_mode, setup_radio, setup_radio_with_bluetooth, wifi_task, RadioSpi,
+        SharedControl,
+    },
+};
+
+// Declares `Peripherals` with the given fields and moves them out of `embassy_rp::Peripherals`
+macro_rules! remaining_peripherals {
+    ($($(#[$cfg:meta])? $name:ident),* $(,)?) => {
+        // everything the board does not use, named like the fields of `embassy_rp::Peripherals`
+        #[allow(non_snake_case)]
+        pub struct Peripherals {
+            $($(#[$cfg])? pub $name: Peri<'static, peripherals::$name>,)*
+        }
+
+        impl Peripherals {
+            fn split(p: embassy_rp::Peripherals) -> (BoardPeripherals, Self) {
+                let board = BoardPeripherals {
+                    usb: p.USB,
+                    pwr: p.PIN_23,
+                    dio: p.PIN_24,
+                    cs: p.PIN_25,
+                    clk: p.PIN_29,
+                    pio: p.PIO0,
+                    dma: p.DMA_CH0,
+                };
+                let rest = Self {
+                    $($(#[$cfg])? $name: p.$name,)*
+                };
+                (board, rest)
+            }
+        }
+    };
+}
+
+struct BoardPeripherals {
+    usb: Peri<'static, USB>,
+    pwr: Peri<'static, peripherals::PIN_23>,
+    dio: Peri<'static, peripherals::PIN_24>,
+    cs: Peri<'static, peripherals::PIN_25>,
+    clk: Peri<'static, peripherals::PIN_29>,
+    pio: Peri<'static, PIO0>,
+    dma: Peri<'static, peripherals::DMA_CH0>,
+}
+
+// GP23 (radio power), GP24 (spi data), GP25 (spi chip select), GP29 (spi clock), PIO0, DMA_CH0 and USB are used by the board
+remaining_peripherals!(
+    PIN_0,
+    PIN_1,
+    PIN_2,
+    PIN_3,
+    PIN_4,
+    PIN_5,
+    PIN_6,
+    PIN_7,
+    PIN_8,
+    PIN_9,
+    PIN_10,
+    PIN_11,
+    PIN_12,
+    PIN_13,
+    PIN_14,
+    PIN_15,
+    PIN_16,
+    PIN_17,
+    PIN_18,
+    PIN_19,
+    PIN_20,
+    PIN_21,
+    PIN_22,
+    PIN_26,
+    PIN_27,
+    PIN_28,
+    UART0,
+    UART1,
+    SPI0,
+    SPI1,
+    I2C0,
+    I2C1,
+    DMA_CH1,
+    DMA_CH2,
+    DMA_CH3,
+    DMA_CH4,
+    DMA_CH5,
+    DMA_CH6,
+    DMA_CH7,
+    DMA_CH8,
+    DMA_CH9,
+    DMA_CH10,
+    DMA_CH11,
+    #[cfg(feature = "rp235x")]
+    DMA_CH12,
+    #[cfg(feature = "rp235x")]
+    DMA_CH13,
+    #[cfg(feature = "rp235x")]
+    DMA_CH14,
+    #[cfg(feature = "rp235x")]
+    DMA_CH15,
+    PWM_SLICE0,
+    PWM_SLICE1,
+    PWM_SLICE2,
+    PWM_SLICE3,
+    PWM_SLICE4,
+    PWM_SLICE5,
+    PWM_SLICE6,
+    PWM_SLICE7,
+    #[cfg(feature = "rp235x")]
+    PWM_SLICE8,
+    #[cfg(feature = "rp235x")]
+    PWM_SLICE9,
+    #[cfg(feature = "rp235x")]
+    PWM_SLICE10,
+    #[cfg(feature = "rp235x")]
+    PWM_SLICE11,
+    RTC,
+    FLASH,
+    ADC,
+    ADC_TEMP_SENSOR,
+    CORE1,
+    PIO1,
+    #[cfg(feature = "rp235x")]
+    PIO2,
+    WATCHDOG,
+    BOOTSEL,
+    #[cfg(feature = "rp235x")]
+    TRNG,
+);
+
+// A Pico 2 W with logging over usb and the radio running. The network is left to the application because it needs
+// the application's ip address and port:
+// `let socket = setup_network(&spawner, board.net_device, board.control, local_ip, LOCAL_PORT).await;`
+pub struct PicoW2Board {
+    pub control: &'static SharedControl,
+    pub net_device: NetDriver<'static>,
+    pub p: Peripherals,
+}
+
+impl PicoW2Board {
+    // `irqs` comes from `bind_interrupts!`, the board needs the usb and PIO0 interrupts:
+    // `USBCTRL_IRQ => usb::InterruptHandler<USB>;` and `PIO0_IRQ_0 => pio::InterruptHandler<PIO0>;`
+    pub async fn init<I>(spawner: &Spawner, p: embassy_rp::Peripherals, irqs: I) -> Self
+    where
+        I: Binding<USBCTRL_IRQ, usb::InterruptHandler<USB>>
+            + Binding<PIO0_IRQ_0, pio::InterruptHandler<PIO0>>
+            + Copy,
+    {
+        let (p, pwr, spi) = setup_board(spawner, p, irqs);
+        let (net_device, control) =
+            setup_radio(spawner, pwr, spi, wifi_task, default_power_mode()).await;
+
+        Self {
+            control,
+            net_device,
+            p,
+        }
+    }
+
+    // Like `init` but with the bluetooth controller running as well, the returned driver is passed to `ble::setup_ble`
+    pub async fn init_with_bluetooth<I>(
+        spawner: &Spawner,
+        p: embassy_rp::Peripherals,
+        irqs: I,
+    ) -> (Self, BtDriver<'static>)
+    where
+        I: Binding<USBCTRL_IRQ, usb::InterruptHandler<USB>>
+            + Binding<PIO0_IRQ_0, pio::InterruptHandler<PIO0>>
+            + Copy,
+    {
+        let (p, pwr, spi) = setup_board(spawner, p, irqs);
+        let (net_device, bt_device, control) =
+            setup_radio_with_bluetooth(spawner, pwr, spi, wifi_task, default_power_mode()).await;
+
+        let board = Self {
+            control,
+            net_device,
+            p,
+        };
+        (board, bt_device)
+    }
+}
+
+// starts logging and returns the radio power pin and spi bus
+fn setup_board<I>(
+    spawner: &Spawner,
+    p: embassy_rp::Peripherals,
+    irqs: I,
+) -> (Peripherals, Output<'static>, RadioSpi<PIO0, 0, DMA_CH0>)
+where
+    I: Binding<USBCTRL_IRQ, usb::InterruptHandler<USB>>
+        + Binding<PIO0_IRQ_0, pio::InterruptHandler<PIO0>>
+        + Copy,
+{
+    let (board, p) = Peripherals::split(p);
+
+    // setup logging over usb serial port
+    let driver = usb::Driver::new(board.usb, irqs);
+    setup_logging(spawner, driver);
+
+    // setup spi bus for wifi modem
+    let pwr = Output::new(board.pwr, Level::Low);
+    let cs = Output::new(board.cs, Level::High);
+    let mut pio = Pio::new(board.pio, irqs);
+    let spi = PioSpi::new(
+        &mut pio.common,
+        pio.sm0,
+        CLOCK_DIVIDER,
+        pio.irq0,
+        cs,
+        board.dio,
+        board.clk,
+        board.dma,
+    );
+
+    (p, pwr, spi)
+}
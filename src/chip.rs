@@ -0,0 +1,42 @@
+// What differs between the RP2350 of the Pico 2 W and the RP2040 of the original Pico W.
+// The chip is selected with the `rp235x` (default) or `rp2040` feature, see "Pico W (RP2040)" in the readme.
+
+#[cfg(all(feature = "rp235x", feature = "rp2040"))]
+compile_error!("only one of the `rp235x` and `rp2040` features can be enabled");
+
+#[cfg(not(any(feature = "rp235x", feature = "rp2040")))]
+compile_error!("enable the `rp235x` or the `rp2040` feature");
+
+pub(crate) const REBOOT_TYPE_NORMAL: u32 = 0x0000;
+pub(crate) const REBOOT_TYPE_BOOTSEL: u32 = 0x0002;
+
+// the usb product id the pico sdk uses for its serial port, picotool only looks for the reset interface on these
+#[cfg(feature = "rp235x")]
+pub(crate) const USB_PRODUCT_ID: u16 = 0x0009;
+#[cfg(feature = "rp2040")]
+pub(crate) const USB_PRODUCT_ID: u16 = 0x000a;
+
+// Reboots `delay_ms` after the call so that e.g. a usb request can still be acknowledged.
+// See reboot section "5.4.8.24" of the rp2350 datasheet.
+#[cfg(feature = "rp235x")]
+pub(crate) fn reboot(reboot_type: u32, delay_ms: u32) {
+    embassy_rp::rom_data::reboot(reboot_type, delay_ms, 0, 0);
+}
+
+// The RP2040 boot rom cannot delay a reboot so this reboots straight away
+#[cfg(feature = "rp2040")]
+pub(crate) fn reboot(reboot_type: u32, _delay_ms: u32) {
+    if reboot_type == REBOOT_TYPE_BOOTSEL {
+        embassy_rp::rom_data::reset_to_usb_boot(0, 0);
+    } else {
+        // nothing else runs after this so taking the watchdog cannot conflict with the application
+        let watchdog = unsafe { embassy_rp::peripherals::WATCHDOG::steal() };
+        embassy_rp::watchdog::Watchdog::new(watchdog).trigger_reset();
+    }
+}
+
+// the flash chip on the board, the last sector holds the Wi-Fi credentials (see memory-rp235x.x and memory-rp2040.x)
+#[cfg(feature = "rp235x")]
+pub(crate) const FLASH_SIZE: usize = 4 * 1024 * 1024;
+#[cfg(feature = "rp2040")]
+pub(crate) const FLASH_SIZE: usize = 2 * 1024 * 1024;
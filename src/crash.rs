@@ -0,0 +1,337 @@
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+    ptr::{addr_of, addr_of_mut},
+};
+
+use embassy_time::Instant;
+
+const MAGIC: u32 = 0x4352_5348; // "CRSH"
+const KIND_PANIC: u32 = 0;
+const KIND_HARD_FAULT: u32 = 1;
+const MESSAGE_LEN: usize = 160;
+const FILE_LEN: usize = 64;
+const FAULT_WORDS: usize = 7;
+// magic, kind, uptime, fault registers, line, message length, file length, message, file and checksum
+const REPORT_WORDS: usize = 1 + 1 + 2 + FAULT_WORDS + 3 + MESSAGE_LEN / 4 + FILE_LEN / 4 + 1;
+
+// A crash report kept in a RAM region that is not initialised at startup (see `.crash_report` in memory-rp235x.x and memory-rp2040.x)
+// so that it survives the reboot that follows a panic or a hard fault. The checksum guards against reading garbage
+// after a power cycle or if the region was overwritten by the bootloader.
+#[derive(Clone, Copy)]
+pub struct CrashReport {
+    kind: u32,
+    uptime_ms: u64,
+    fault: FaultRegisters,
+    line: u32,
+    message_len: u32,
+    file_len: u32,
+    message: [u8; MESSAGE_LEN],
+    file: [u8; FILE_LEN],
+}
+
+// The report as plain words. The section is NOLOAD, so the zeros are never written and the words keep whatever the RAM
+// held before the reboot. Every bit pattern is a valid `u32`, so reading them is fine even after a power cycle, the
+// magic and checksum then tell whether they are a report.
+#[link_section = ".crash_report"]
+static mut CRASH_REPORT: [u32; REPORT_WORDS] = [0; REPORT_WORDS];
+
+// number of crashes since the firmware last ran long enough to be considered stable,
+// stored next to its bitwise complement so that random RAM contents are not mistaken for a count
+#[link_section = ".crash_report"]
+static mut CRASH_COUNT: [u32; 2] = [0; 2];
+
+impl CrashReport {
+    fn new() -> Self {
+        Self {
+            kind: KIND_PANIC,
+            uptime_ms: Instant::now().as_millis(),
+            fault: FaultRegisters::default(),
+            line: 0,
+            message_len: 0,
+            file_len: 0,
+            message: [0; MESSAGE_LEN],
+            file: [0; FILE_LEN],
+        }
+    }
+
+    pub fn uptime_ms(&self) -> u64 {
+        self.uptime_ms
+    }
+
+    pub fn message(&self) -> &str {
+        as_str(&self.message, self.message_len)
+    }
+
+    pub fn file(&self) -> &str {
+        as_str(&self.file, self.file_len)
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    // the registers captured by the hard fault handler, `None` for a panic
+    pub fn fault(&self) -> Option<&FaultRegisters> {
+        (self.kind == KIND_HARD_FAULT).then_some(&self.fault)
+    }
+
+    fn as_words(&self) -> [u32; REPORT_WORDS] {
+        let header = [
+            MAGIC,
+            self.kind,
+            self.uptime_ms as u32,
+            (self.uptime_ms >> 32) as u32,
+        ];
+        let words = header
+            .into_iter()
+            .chain(self.fault.as_array())
+            .chain([self.line, self.message_len, self.file_len])
+            .chain(self.message.chunks_exact(4).map(word))
+            .chain(self.file.chunks_exact(4).map(word));
+
+        let mut report = [0; REPORT_WORDS];
+        for (slot, word) in report.iter_mut().zip(words) {
+            *slot = word;
+        }
+        report[REPORT_WORDS - 1] = checksum(&report[..REPORT_WORDS - 1]);
+        report
+    }
+
+    // `None` unless the words hold a report written by `as_words`
+    fn from_words(words: &[u32; REPORT_WORDS]) -> Option<Self> {
+        let (body, check) = words.split_at(REPORT_WORDS - 1);
+        if body[0] != MAGIC || check[0] != checksum(body) {
+            return None;
+        }
+
+        let mut words = body[1..].iter().copied();
+        let mut next = || words.next().unwrap_or_default();
+        let kind = next();
+        let uptime_ms = u64::from(next()) | (u64::from(next()) << 32);
+        let fault = FaultRegisters {
+            pc: next(),
+            lr: next(),
+            xpsr: next(),
+            cfsr: next(),
+            hfsr: next(),
+            mmfar: next(),
+            bfar: next(),
+        };
+        let (line, message_len, file_len) = (next(), next(), next());
+        let mut message = [0; MESSAGE_LEN];
+        for bytes in message.chunks_exact_mut(4) {
+            bytes.copy_from_slice(&next().to_le_bytes());
+        }
+        let mut file = [0; FILE_LEN];
+        for bytes in file.chunks_exact_mut(4) {
+            bytes.copy_from_slice(&next().to_le_bytes());
+        }
+
+        let valid = (kind == KIND_PANIC || kind == KIND_HARD_FAULT)
+            && message_len as usize <= MESSAGE_LEN
+            && file_len as usize <= FILE_LEN;
+        valid.then_some(Self {
+            kind,
+            uptime_ms,
+            fault,
+            line,
+            message_len,
+            file_len,
+            message,
+            file,
+        })
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (secs, millis) = (self.uptime_ms / 1000, self.uptime_ms % 1000);
+        match self.fault() {
+            Some(fault) => write!(f, "hard fault after {secs}.{millis:03}s: {fault}"),
+            None => write!(
+                f,
+                "panic at {}:{} after {secs}.{millis:03}s: {}",
+                self.file(),
+                self.line(),
+                self.message()
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CrashReport {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        let (secs, millis) = (self.uptime_ms / 1000, self.uptime_ms % 1000);
+        match self.fault() {
+            Some(fault) => {
+                defmt::write!(f, "hard fault after {}.{=u64:03}s: {}", secs, millis, fault)
+            }
+            None => defmt::write!(
+                f,
+                "panic at {=str}:{} after {}.{=u64:03}s: {=str}",
+                self.file(),
+                self.line(),
+                secs,
+                millis,
+                self.message()
+            ),
+        }
+    }
+}
+
+// the exception frame and fault status registers at the time of a hard fault
+#[derive(Clone, Copy, Default)]
+pub struct FaultRegisters {
+    pub pc: u32,
+    pub lr: u32,
+    pub xpsr: u32,
+    // configurable fault status (MemManage, BusFault and UsageFault flags)
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub mmfar: u32,
+    pub bfar: u32,
+}
+
+impl FaultRegisters {
+    fn as_array(&self) -> [u32; FAULT_WORDS] {
+        [
+            self.pc, self.lr, self.xpsr, self.cfsr, self.hfsr, self.mmfar, self.bfar,
+        ]
+    }
+}
+
+impl fmt::Display for FaultRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pc={:#010x} lr={:#010x} xpsr={:#010x} cfsr={:#010x} hfsr={:#010x} mmfar={:#010x} bfar={:#010x}",
+            self.pc, self.lr, self.xpsr, self.cfsr, self.hfsr, self.mmfar, self.bfar
+        )
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for FaultRegisters {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "pc={=u32:#010x} lr={=u32:#010x} xpsr={=u32:#010x} cfsr={=u32:#010x} hfsr={=u32:#010x} mmfar={=u32:#010x} bfar={=u32:#010x}",
+            self.pc, self.lr, self.xpsr, self.cfsr, self.hfsr, self.mmfar, self.bfar
+        )
+    }
+}
+
+// called from the panic handler, must not panic itself
+pub(crate) fn record_panic(info: &PanicInfo) {
+    let mut report = CrashReport::new();
+
+    let mut message = Truncate::new(&mut report.message);
+    let _ = write!(message, "{}", info.message());
+    report.message_len = message.len as u32;
+
+    if let Some(location) = info.location() {
+        let mut file = Truncate::new(&mut report.file);
+        let _ = file.write_str(location.file());
+        report.file_len = file.len as u32;
+        report.line = location.line();
+    }
+
+    save(report);
+}
+
+// called from the hard fault handler
+pub(crate) fn record_hard_fault(fault: FaultRegisters) {
+    let mut report = CrashReport::new();
+    report.kind = KIND_HARD_FAULT;
+    report.fault = fault;
+    save(report);
+}
+
+fn save(report: CrashReport) {
+    write_words(addr_of_mut!(CRASH_REPORT), &report.as_words());
+    set_consecutive_crashes(consecutive_crashes().saturating_add(1));
+}
+
+// returns the report of the crash that caused the last reboot, if any
+pub fn last_crash() -> Option<CrashReport> {
+    CrashReport::from_words(&read_words(addr_of!(CRASH_REPORT)))
+}
+
+pub fn clear() {
+    write_words(addr_of_mut!(CRASH_REPORT), &[0; REPORT_WORDS]);
+    set_consecutive_crashes(0);
+}
+
+pub fn consecutive_crashes() -> u32 {
+    let [count, check] = read_words(addr_of!(CRASH_COUNT));
+    if count == !check {
+        count
+    } else {
+        0
+    }
+}
+
+pub(crate) fn set_consecutive_crashes(count: u32) {
+    write_words(addr_of_mut!(CRASH_COUNT), &[count, !count]);
+}
+
+// volatile so that the compiler does not assume the statics still hold the zeros they were declared with
+fn read_words<const N: usize>(words: *const [u32; N]) -> [u32; N] {
+    let mut values = [0; N];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = unsafe { words.cast::<u32>().add(i).read_volatile() };
+    }
+    values
+}
+
+fn write_words<const N: usize>(words: *mut [u32; N], values: &[u32; N]) {
+    for (i, value) in values.iter().enumerate() {
+        unsafe { words.cast::<u32>().add(i).write_volatile(*value) };
+    }
+}
+
+// FNV-1a over the little endian bytes of `words`
+fn checksum(words: &[u32]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for b in words.iter().flat_map(|word| word.to_le_bytes()) {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+fn word(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn as_str(buf: &[u8], len: u32) -> &str {
+    let buf = &buf[..(len as usize).min(buf.len())];
+    match core::str::from_utf8(buf) {
+        Ok(s) => s,
+        // truncation can split a multi-byte character
+        Err(e) => core::str::from_utf8(&buf[..e.valid_up_to()]).unwrap_or_default(),
+    }
+}
+
+// writes as much as fits into a fixed size buffer and silently drops the rest
+struct Truncate<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Truncate<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+}
+
+impl Write for Truncate<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
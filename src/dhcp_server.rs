@@ -0,0 +1,112 @@
+use core::net::Ipv4Addr;
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    Stack,
+};
+
+use embassy_time::Instant;
+
+use crate::{
+    dhcp::{
+        parse_request, reply_destination, write_reply, MessageType, ServerOptions, CLIENT_PORT,
+        SERVER_PORT,
+    },
+    dhcp_leases::{LeaseTable, Pool},
+    fmt::HexBytes,
+};
+
+// A small DHCP server for the access point mode (see `network::setup_access_point`). The leases are kept in memory
+// only, so clients keep their address across a reboot of the board only if it is still free (see `dhcp_leases.rs`).
+
+// the network the server hands out addresses on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpServerConfig {
+    // the server address, subnet mask, lease time, router and dns servers given to clients
+    pub options: ServerOptions,
+    // must not contain the server address
+    pub pool: Pool,
+}
+
+impl DhcpServerConfig {
+    // The /24 network of `server` which is also the router, with a pool of 16 addresses next to it (see `Pool::in_subnet`)
+    // and one hour leases
+    pub fn new(server: Ipv4Addr) -> Self {
+        let subnet_mask = Ipv4Addr::new(255, 255, 255, 0);
+        Self {
+            options: ServerOptions {
+                server,
+                subnet_mask,
+                router: Some(server),
+                dns_servers: heapless::Vec::new(),
+                lease_time_secs: 60 * 60,
+            },
+            pool: Pool::in_subnet(server, subnet_mask, 16),
+        }
+    }
+}
+
+pub async fn run_dhcp_server(stack: Stack<'static>, config: DhcpServerConfig) -> ! {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 1024];
+    let mut tx_buffer = [0; 1024];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(SERVER_PORT).unwrap();
+
+    let server = config.options.server;
+    let mut leases = LeaseTable::new(config.pool, config.options.lease_time_secs);
+
+    info!(
+        "dhcp server handing out {} addresses from {}",
+        config.pool.size, config.pool.start
+    );
+    let mut buf = [0; 1024];
+    loop {
+        let len = match socket.recv_from(&mut buf).await {
+            Ok((len, _)) => len,
+            Err(e) => {
+                warn!("dhcp receive error: {:?}", e);
+                continue;
+            }
+        };
+
+        let request = match parse_request(&buf[..len]) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("ignoring dhcp message: {}", e);
+                continue;
+            }
+        };
+
+        let now_secs = Instant::now().as_secs();
+        let Some((message_type, address)) = leases.handle(&request, server, now_secs) else {
+            continue;
+        };
+        match message_type {
+            MessageType::Ack => info!(
+                "leased {} to {} ({})",
+                address,
+                HexBytes(&request.mac),
+                request.hostname.unwrap_or("no hostname")
+            ),
+            MessageType::Nak => info!(
+                "refused the address requested by {}",
+                HexBytes(&request.mac)
+            ),
+            _ => {}
+        }
+
+        let reply = write_reply(&request, message_type, address, &config.options);
+        let destination = (reply_destination(&request, message_type), CLIENT_PORT);
+        if let Err(e) = socket.send_to(&reply, destination).await {
+            warn!("dhcp send error: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+// Logging macros used throughout this crate and its examples. They forward to `log` by default, to defmt over RTT
+// when the `defmt` feature is enabled, and expand to nothing with the `no-logging` feature (the arguments are still
+// type checked but never evaluated).
+//
+// Inside this crate (`#[macro_use]` in lib.rs, so modules do not import them) they expand to defmt's own macros, which
+// leave the format strings on the host and only send the arguments. The arguments therefore need `defmt::Format` as
+// well as `Display` or `Debug`, and only the format specifiers both understand can be used (`{}` and `{:?}`, no widths
+// or `{:02x?}`, see `HexBytes`). The `target:` form of `log` is not available with defmt.
+//
+// The exported macros used by the examples cannot do that: defmt's macros reject crates whose names start with a
+// digit (`01_logs` and so on), so with defmt the examples' messages are formatted on the device and sent as a string.
+
+use core::fmt::{self, Write};
+
+#[cfg(feature = "defmt")]
+use heapless::String;
+#[cfg(feature = "defmt")]
+use log::Level;
+
+#[cfg(not(any(feature = "defmt", feature = "no-logging")))]
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::__log::trace!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::fmt::defmt_log($crate::__log::Level::Trace, format_args!($($arg)*))
+    };
+}
+
+#[cfg(not(any(feature = "defmt", feature = "no-logging")))]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::__log::debug!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::fmt::defmt_log($crate::__log::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+#[cfg(not(any(feature = "defmt", feature = "no-logging")))]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::__log::info!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::fmt::defmt_log($crate::__log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+#[cfg(not(any(feature = "defmt", feature = "no-logging")))]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::__log::warn!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::fmt::defmt_log($crate::__log::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+#[cfg(not(any(feature = "defmt", feature = "no-logging")))]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::__log::error!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::fmt::defmt_log($crate::__log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+#[cfg(feature = "no-logging")]
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "no-logging")]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "no-logging")]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "no-logging")]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(feature = "no-logging")]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+// The versions used inside this crate, they shadow the exported ones above (not every level is used)
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        ::defmt::trace!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[allow(unused_macros)]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        ::defmt::debug!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[allow(unused_macros)]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        ::defmt::info!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[allow(unused_macros)]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        ::defmt::warn!($($arg)*)
+    };
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no-logging")))]
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        ::defmt::error!($($arg)*)
+    };
+}
+
+#[cfg(feature = "defmt")]
+pub fn defmt_log(level: Level, args: fmt::Arguments<'_>) {
+    // messages that do not fit are truncated
+    let mut message: String<256> = String::new();
+    let _ = message.write_fmt(args);
+
+    match level {
+        Level::Error => defmt::error!("{=str}", message),
+        Level::Warn => defmt::warn!("{=str}", message),
+        Level::Info => defmt::info!("{=str}", message),
+        Level::Debug => defmt::debug!("{=str}", message),
+        Level::Trace => defmt::trace!("{=str}", message),
+    }
+}
+
+// Bytes written as colon separated hex (e.g. a mac address `28:cd:c1:0a:3b:4f`) by both `log` and defmt
+pub struct HexBytes<'a>(pub &'a [u8]);
+
+impl fmt::Display for HexBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(':')?;
+            }
+            write!(f, "{b:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for HexBytes<'_> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        for (i, b) in self.0.iter().enumerate() {
+            if i > 0 {
+                defmt::write!(f, ":");
+            }
+            defmt::write!(f, "{=u8:02x}", b);
+        }
+    }
+}
@@ -1,18 +1,38 @@
 #![no_std]
 
-use embassy_rp::{block::ImageDef, rom_data::reboot};
-use logging::REBOOT_TYPE_BOOTSEL;
+#[cfg(feature = "defmt")]
+use defmt_rtt as _;
+#[cfg(feature = "rp235x")]
+use embassy_rp::block::ImageDef;
 
+#[doc(hidden)]
+#[macro_use]
+pub mod fmt;
+
+pub mod ble;
+pub mod board;
+mod chip;
+pub mod crash;
+pub mod dhcp_server;
 pub mod logging;
 pub mod network;
+pub mod panic_policy;
+pub mod provisioning;
 pub mod radio;
+pub mod shell;
+
+// the parts that do not touch the hardware, they are tested on a PC (see the `pico2w-logic` crate)
+pub use pico2w_logic::{
+    command_protocol, dhcp, dhcp_leases, led_service, line_buffer, log_filter, log_format,
+    provisioning_service, rfc5424, wifi_credentials, wifi_scan,
+};
 
-#[panic_handler]
-fn core_panic(_info: &core::panic::PanicInfo) -> ! {
-    reboot(REBOOT_TYPE_BOOTSEL, 100, 0, 0);
-    loop {}
-}
+// used by the logging macros in `fmt.rs`
+#[doc(hidden)]
+pub use log as __log;
 
+// tells the RP2350 boot rom how to start the firmware, the RP2040 uses the second stage bootloader from embassy-rp instead
+#[cfg(feature = "rp235x")]
 #[link_section = ".start_block"]
 #[used]
 pub static IMAGE_DEF: ImageDef = ImageDef::secure_exe();
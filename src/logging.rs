@@ -1,37 +1,275 @@
-use core::str;
+use core::cell::RefCell;
 
+use cortex_m::peripheral::{scb::VectActive, SCB};
 use embassy_executor::Spawner;
-use embassy_rp::{peripherals::USB, rom_data::reboot, usb::Driver};
-use embassy_usb_logger::ReceiverHandler;
+use embassy_rp::{peripherals::USB, usb::Driver};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    once_lock::OnceLock,
+};
+use embassy_time::{Duration, Instant};
+use heapless::String;
+use log::{LevelFilter, Log, Metadata, Record};
+use portable_atomic::{AtomicU32, AtomicU8, AtomicUsize, Ordering};
 
-pub(crate) const REBOOT_TYPE_BOOTSEL: u32 = 0x0002;
+use crate::{
+    crash,
+    log_filter::{FilterError, LogFilter},
+    log_format,
+    panic_policy::setup_crash_counter,
+    shell::setup_shell,
+};
 
-#[embassy_executor::task]
-async fn logger_task(driver: Driver<'static, USB>) {
-    embassy_usb_logger::run!(1024, log::LevelFilter::Info, driver, Handler);
+#[cfg(feature = "log-frames")]
+mod frames;
+pub mod ring;
+#[cfg(feature = "rtt")]
+pub mod rtt;
+pub mod syslog;
+pub mod usb;
+
+// both create the RTT control block
+#[cfg(all(feature = "rtt", feature = "defmt"))]
+compile_error!("the `rtt` and `defmt` features cannot be enabled together");
+
+#[cfg(all(feature = "no-logging", any(feature = "rtt", feature = "defmt")))]
+compile_error!("the `no-logging` feature cannot be combined with `rtt` or `defmt`");
+
+pub use usb::wait_for_host;
+
+// the log filter used at startup, e.g. `PICO_LOG="info,rp_pico2w_examples::network=debug"` (see `.cargo/config.toml`)
+const DEFAULT_FILTER: &str = match option_env!("PICO_LOG") {
+    Some(filter) => filter,
+    None => "info",
+};
+
+const MAX_SINKS: usize = 4;
+
+static FILTER: Mutex<CriticalSectionRawMutex, RefCell<LogFilter>> =
+    Mutex::new(RefCell::new(LogFilter::new(LevelFilter::Info)));
+
+static SINKS: [OnceLock<SinkSlot>; MAX_SINKS] = [const { OnceLock::new() }; MAX_SINKS];
+static SINK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    // no room right now, the record may fit if we wait for the sink to catch up
+    Full,
 }
 
-pub fn setup_logging(spawner: &Spawner, driver: Driver<'static, USB>) {
-    spawner.spawn(logger_task(driver)).unwrap();
+// A destination for log records. Records are fanned out to every sink whose level allows them.
+// `write` is called from whatever context logged the record so it must never wait.
+pub trait Sink: Sync {
+    fn name(&self) -> &'static str;
+    fn write(&self, record: &Record) -> Result<(), SinkError>;
 }
 
-pub struct Handler;
+// What to do when a sink is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    // drop the record and count it
+    Drop,
+    // busy-wait up to the given time for room before dropping the record. This only helps if the sink is drained
+    // outside the thread mode executor (e.g. by a debug probe or an interrupt executor) and is skipped in interrupts.
+    Block(Duration),
+}
+
+struct SinkSlot {
+    sink: &'static dyn Sink,
+    level: AtomicU8,
+    overflow: Overflow,
+    dropped: AtomicU32,
+}
 
-impl ReceiverHandler for Handler {
-    async fn handle_data(&self, data: &[u8]) {
-        if let Ok(data) = str::from_utf8(data) {
-            let data = data.trim();
+impl SinkSlot {
+    fn write(&self, record: &Record) {
+        let mut result = self.sink.write(record);
 
-            // If you are using elf2uf2-term with the '-t' flag, then when closing the serial monitor,
-            // this will automatically put the pico into boot mode
-            if data == "elf2uf2-term" {
-                // see reboot section "5.4.8.24" of rp2350 datasheet
-                reboot(REBOOT_TYPE_BOOTSEL, 100, 0, 0);
+        if let (Err(SinkError::Full), Overflow::Block(timeout)) = (result, self.overflow) {
+            if SCB::vect_active() == VectActive::ThreadMode {
+                let deadline = Instant::now() + timeout;
+                while result == Err(SinkError::Full) && Instant::now() < deadline {
+                    result = self.sink.write(record);
+                }
             }
         }
+
+        if result.is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn level(&self) -> LevelFilter {
+        level_from_u8(self.level.load(Ordering::Relaxed))
+    }
+}
+
+// a summary of a sink for the `log-sink` shell command
+pub struct SinkInfo {
+    pub name: &'static str,
+    pub level: LevelFilter,
+    pub overflow: Overflow,
+    pub dropped: u32,
+}
+
+pub fn setup_logging(spawner: &Spawner, driver: Driver<'static, USB>) {
+    // nothing is started so the usb stack, the shell and the log sinks are left out of the binary
+    if cfg!(feature = "no-logging") {
+        setup_crash_counter(spawner);
+        return;
+    }
+
+    usb::setup_usb_logging(spawner, driver);
+    setup(spawner);
+}
+
+// Logging without the usb serial port (and therefore without the shell), for example to measure power
+// with only a debug probe attached. Enable the `rtt` feature to see the log messages.
+pub fn setup_logging_without_usb(spawner: &Spawner) {
+    if cfg!(feature = "no-logging") {
+        setup_crash_counter(spawner);
+        return;
+    }
+
+    setup(spawner);
+}
+
+fn setup(spawner: &Spawner) {
+    let filter_result = set_filter(DEFAULT_FILTER);
+    set_logger();
+
+    #[cfg(feature = "rtt")]
+    rtt::setup_rtt_logging();
+    ring::setup_ring_logging();
+    setup_shell(spawner);
+    setup_crash_counter(spawner);
+
+    if let Err(e) = filter_result {
+        warn!("invalid PICO_LOG filter '{}': {}", DEFAULT_FILTER, e);
+    }
+
+    if let Some(report) = crash::last_crash() {
+        error!("last reboot was caused by a {}", report);
+        error!("type 'crash clear' to clear this report");
+    }
+}
+
+// adds a sink that receives every record allowed by both the log filter and `level`
+pub fn add_sink(sink: &'static dyn Sink, level: LevelFilter, overflow: Overflow) {
+    let index = SINK_COUNT.fetch_add(1, Ordering::Relaxed);
+    let slot = SinkSlot {
+        sink,
+        level: AtomicU8::new(level as u8),
+        overflow,
+        dropped: AtomicU32::new(0),
+    };
+
+    if index >= MAX_SINKS || SINKS[index].init(slot).is_err() {
+        warn!("log sink '{}' not added, too many sinks", sink.name());
+    }
+}
+
+pub fn set_sink_level(name: &str, level: LevelFilter) -> bool {
+    match sinks().find(|slot| slot.sink.name() == name) {
+        Some(slot) => {
+            slot.level.store(level as u8, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+pub fn sink_info() -> impl Iterator<Item = SinkInfo> {
+    sinks().map(|slot| SinkInfo {
+        name: slot.sink.name(),
+        level: slot.level(),
+        overflow: slot.overflow,
+        dropped: slot.dropped.load(Ordering::Relaxed),
+    })
+}
+
+fn sinks() -> impl Iterator<Item = &'static SinkSlot> {
+    SINKS.iter().filter_map(OnceLock::try_get)
+}
+
+// replaces the log filter at runtime, for example `info,rp_pico2w_examples::network=debug`
+pub fn set_filter(spec: &str) -> Result<(), FilterError> {
+    let filter = LogFilter::parse(spec)?;
+    set_max_level(filter.max_level());
+    FILTER.lock(|f| *f.borrow_mut() = filter);
+    Ok(())
+}
+
+// Without atomic compare-and-swap (the RP2040) `log` only has racy versions of these. The logger is set once during setup
+// and the filter is changed from thread mode (setup and the shell) so they do not race.
+#[cfg(target_has_atomic = "ptr")]
+fn set_logger() {
+    let _ = log::set_logger(&FanoutLogger);
+}
+
+#[cfg(not(target_has_atomic = "ptr"))]
+fn set_logger() {
+    let _ = unsafe { log::set_logger_racy(&FanoutLogger) };
+}
+
+#[cfg(target_has_atomic = "ptr")]
+fn set_max_level(level: LevelFilter) {
+    log::set_max_level(level);
+}
+
+#[cfg(not(target_has_atomic = "ptr"))]
+fn set_max_level(level: LevelFilter) {
+    unsafe { log::set_max_level_racy(level) };
+}
+
+pub fn filter() -> LogFilter {
+    FILTER.lock(|f| f.borrow().clone())
+}
+
+// the plain text format used by the usb, ring buffer and rtt sinks, see `log_format`. A record that does not fit is
+// truncated but still ends with "\r\n".
+pub(crate) fn write_line<const N: usize>(line: &mut String<N>, record: &Record) {
+    let module = record.module_path().unwrap_or(record.target());
+    let task = (record.target() != module).then_some(record.target());
+    log_format::write_record(
+        line,
+        Instant::now().as_millis(),
+        record.level(),
+        module,
+        task,
+        *record.args(),
+    );
+}
+
+fn level_from_u8(level: u8) -> LevelFilter {
+    match level {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
     }
+}
+
+struct FanoutLogger;
 
-    fn new() -> Self {
-        Self
+impl Log for FanoutLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        FILTER.lock(|f| f.borrow().enabled(metadata.target(), metadata.level()))
     }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        for slot in sinks() {
+            if record.level() <= slot.level() {
+                slot.write(record);
+            }
+        }
+    }
+
+    fn flush(&self) {}
 }
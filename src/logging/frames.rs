@@ -0,0 +1,77 @@
+use core::fmt::Write;
+
+use embassy_time::Instant;
+use heapless::{String, Vec};
+use log::Record;
+use log_frame::{encode, Frame, Level, LogRecord, MAX_FRAME_LEN};
+
+use super::usb::{write_before, PIPE, REPLY_TIMEOUT};
+
+// the usb serial port sends frames instead of lines of text, see the `log-frame` crate
+pub(super) const MAX_RECORD_LEN: usize = MAX_FRAME_LEN;
+// longer messages are truncated so that every record fits into a frame
+const MAX_MESSAGE_LEN: usize = 256;
+
+pub(super) fn encode_record(record: &Record) -> Vec<u8, MAX_RECORD_LEN> {
+    let mut message: String<MAX_MESSAGE_LEN> = String::new();
+    let _ = write!(message, "{}", record.args());
+
+    let module = record.module_path().unwrap_or(record.target());
+    let task = (record.target() != module).then_some(record.target());
+    encode_frame(&Frame::Log(LogRecord {
+        uptime_ms: Instant::now().as_millis(),
+        level: level(record.level()),
+        module,
+        task,
+        message: &message,
+    }))
+}
+
+// a warning from the usb logger itself, it does not go through the log filter or the other sinks
+pub(super) fn encode_notice(message: &str) -> Vec<u8, MAX_RECORD_LEN> {
+    encode_frame(&Frame::Log(LogRecord {
+        uptime_ms: Instant::now().as_millis(),
+        level: Level::Warn,
+        module: module_path!(),
+        task: None,
+        message: truncate(message),
+    }))
+}
+
+// sends a shell reply with one frame per line, see `usb::write_console`
+pub(crate) async fn write_console(text: &str) {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    for line in text.lines().map(|line| line.trim_end_matches('\r')) {
+        let frame = encode_frame(&Frame::Shell(truncate(line)));
+        if !write_before(&PIPE, &frame, deadline).await {
+            break;
+        }
+    }
+}
+
+fn encode_frame(frame: &Frame) -> Vec<u8, MAX_RECORD_LEN> {
+    let mut buf = [0; MAX_FRAME_LEN];
+    // does not fail in practice because messages are truncated, nothing is sent if it does
+    encode(frame, &mut buf)
+        .ok()
+        .and_then(|bytes| Vec::from_slice(bytes).ok())
+        .unwrap_or_default()
+}
+
+fn truncate(text: &str) -> &str {
+    let mut end = text.len().min(MAX_MESSAGE_LEN);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn level(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::Error,
+        log::Level::Warn => Level::Warn,
+        log::Level::Info => Level::Info,
+        log::Level::Debug => Level::Debug,
+        log::Level::Trace => Level::Trace,
+    }
+}
@@ -0,0 +1,61 @@
+use core::{cell::RefCell, fmt::Write};
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use heapless::{Deque, String, Vec};
+use log::{LevelFilter, Record};
+
+use super::{add_sink, write_line, Overflow, Sink, SinkError};
+
+const RING_SIZE: usize = 1024;
+const MAX_RECORD_LEN: usize = 256;
+
+// the most recent log output, kept so that it can be read back with the `log-dump` shell command
+// after something went wrong while nobody was watching the serial port
+static RING: Mutex<CriticalSectionRawMutex, RefCell<Deque<u8, RING_SIZE>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+static RING_SINK: RingSink = RingSink;
+
+struct RingSink;
+
+impl Sink for RingSink {
+    fn name(&self) -> &'static str {
+        "ring"
+    }
+
+    fn write(&self, record: &Record) -> Result<(), SinkError> {
+        let mut line: String<MAX_RECORD_LEN> = String::new();
+        write_line(&mut line, record);
+
+        RING.lock(|ring| {
+            let mut ring = ring.borrow_mut();
+
+            // make room by dropping whole lines so that the buffer always starts at the beginning of a line
+            while ring.capacity() - ring.len() < line.len() {
+                while let Some(b) = ring.pop_front() {
+                    if b == b'\n' {
+                        break;
+                    }
+                }
+            }
+
+            for b in line.bytes() {
+                let _ = ring.push_back(b);
+            }
+        });
+
+        Ok(())
+    }
+}
+
+pub(crate) fn setup_ring_logging() {
+    add_sink(&RING_SINK, LevelFilter::Trace, Overflow::Drop);
+}
+
+// writes the buffered log output, oldest first
+pub fn dump(out: &mut impl Write) {
+    // copy it out first so that the critical section stays short
+    let bytes: Vec<u8, RING_SIZE> = RING.lock(|ring| ring.borrow().iter().copied().collect());
+
+    // only whole records are stored so this is valid utf-8
+    let _ = out.write_str(core::str::from_utf8(&bytes).unwrap_or_default());
+}
@@ -0,0 +1,56 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use heapless::String;
+use log::{LevelFilter, Record};
+use rtt_target::{rtt_init, ChannelMode, UpChannel};
+
+use super::{add_sink, write_line, Overflow, Sink, SinkError};
+
+const MAX_RECORD_LEN: usize = 256;
+
+static CHANNEL: Mutex<CriticalSectionRawMutex, RefCell<Option<UpChannel>>> =
+    Mutex::new(RefCell::new(None));
+static RTT_SINK: RttSink = RttSink;
+
+struct RttSink;
+
+impl Sink for RttSink {
+    fn name(&self) -> &'static str {
+        "rtt"
+    }
+
+    fn write(&self, record: &Record) -> Result<(), SinkError> {
+        let mut line: String<MAX_RECORD_LEN> = String::new();
+        write_line(&mut line, record);
+
+        // the channel skips writes that do not fit so nothing is written when the probe is not keeping up
+        let written = CHANNEL.lock(|channel| match channel.borrow_mut().as_mut() {
+            Some(channel) => channel.write(line.as_bytes()),
+            None => 0,
+        });
+
+        if written == 0 {
+            Err(SinkError::Full)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Sends log records to a debug probe over RTT, e.g. `probe-rs run` shows them as it would defmt output.
+// Must only be called once, the RTT control block is created here.
+pub(crate) fn setup_rtt_logging() {
+    let channels = rtt_init! {
+        up: {
+            0: {
+                size: 1024,
+                mode: ChannelMode::NoBlockSkip,
+                name: "Terminal"
+            }
+        }
+    };
+
+    CHANNEL.lock(|channel| channel.replace(Some(channels.up.0)));
+    add_sink(&RTT_SINK, LevelFilter::Trace, Overflow::Drop);
+}
@@ -0,0 +1,104 @@
+use embassy_executor::Spawner;
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpEndpoint,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::String;
+use log::{LevelFilter, Record};
+
+use super::{add_sink, Overflow, Sink, SinkError};
+use crate::{
+    network,
+    rfc5424::{self, Header},
+};
+
+pub const SYSLOG_PORT: u16 = 514;
+
+const MAX_DATAGRAM_LEN: usize = 256;
+
+const HEADER: Header = Header {
+    hostname: "pico2w",
+    app_name: env!("CARGO_PKG_NAME"),
+};
+
+type Datagram = String<MAX_DATAGRAM_LEN>;
+
+// records logged before the network is up are kept here until they can be sent, enough for the start of the board and
+// joining the network
+static QUEUE: Channel<CriticalSectionRawMutex, Datagram, 32> = Channel::new();
+static SYSLOG_SINK: SyslogSink = SyslogSink;
+
+// Forwards log records as RFC 5424 syslog messages over UDP to `collector`, for example a local
+// `nc -ul 514` or rsyslog. Call this before `PicoW2Board::init` so that boot messages are kept, they are sent once
+// the network is up.
+pub fn setup_syslog(spawner: &Spawner, collector: IpEndpoint) {
+    add_sink(&SYSLOG_SINK, LevelFilter::Trace, Overflow::Drop);
+    spawner.spawn(syslog_task(collector)).unwrap();
+}
+
+struct SyslogSink;
+
+impl Sink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn write(&self, record: &Record) -> Result<(), SinkError> {
+        // messages that do not fit are truncated
+        let mut datagram = Datagram::new();
+        let _ = rfc5424::write_message(
+            &mut datagram,
+            &HEADER,
+            record.level(),
+            Instant::now().as_millis(),
+            record.module_path().unwrap_or(record.target()),
+            *record.args(),
+        );
+
+        QUEUE.try_send(datagram).map_err(|_| SinkError::Full)
+    }
+}
+
+#[embassy_executor::task]
+async fn syslog_task(collector: IpEndpoint) -> ! {
+    // the network stack is created by `setup_network`, which may be called later
+    let stack = loop {
+        if let Some(stack) = network::stack() {
+            break stack;
+        }
+        Timer::after(Duration::from_millis(100)).await;
+    };
+
+    stack.wait_config_up().await;
+
+    let mut rx_buffer = [0u8; 64];
+    let mut tx_buffer = [0u8; 1024];
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).unwrap();
+    info!("sending syslog messages to {}", collector);
+
+    // only report the first of a run of send errors, the warning itself is queued for syslog too
+    let mut failing = false;
+
+    loop {
+        let datagram = QUEUE.receive().await;
+        match socket.send_to(datagram.as_bytes(), collector).await {
+            Ok(()) => failing = false,
+            Err(e) if !failing => {
+                warn!("syslog send error: {:?}", e);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
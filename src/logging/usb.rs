@@ -0,0 +1,430 @@
+use core::{
+    fmt::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use embassy_executor::Spawner;
+use embassy_futures::{
+    join::{join, join4},
+    select::{select, Either},
+};
+use embassy_rp::{peripherals::USB, usb::Driver};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, pipe::Pipe};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embassy_usb::{
+    class::cdc_acm::{CdcAcmClass, ControlChanged, Receiver, Sender, State},
+    control::{OutResponse, Recipient, Request, RequestType},
+    msos::{self, windows_version},
+    types::InterfaceNumber,
+    Builder, Config, Handler, UsbDevice,
+};
+use heapless::String;
+use log::{LevelFilter, Record};
+use static_cell::StaticCell;
+
+#[cfg(feature = "log-frames")]
+pub(crate) use super::frames::write_console;
+#[cfg(feature = "log-frames")]
+use super::frames::{encode_notice, encode_record, MAX_RECORD_LEN};
+use super::{add_sink, sink_info, Overflow, Sink, SinkError};
+use crate::{
+    chip::{reboot, REBOOT_TYPE_BOOTSEL, REBOOT_TYPE_NORMAL, USB_PRODUCT_ID},
+    command_protocol::{self, RequestError},
+    line_buffer::{LineBuffer, LineError},
+    shell::{self, MAX_LINE_LEN},
+};
+
+const MAX_PACKET_SIZE: u16 = 64;
+#[cfg(not(feature = "log-frames"))]
+const MAX_RECORD_LEN: usize = 256;
+
+// If you are using elf2uf2-term with the '-t' flag, then when closing the serial monitor
+// it sends this string which will automatically put the pico into boot mode
+const ELF2UF2_TERM: &str = "elf2uf2-term";
+
+// the reset interface of the pico sdk, used by `picotool reboot` and `picotool load -f`
+const RESET_INTERFACE_CLASS: u8 = 0xff;
+const RESET_INTERFACE_SUB_CLASS: u8 = 0x00;
+const RESET_INTERFACE_PROTOCOL: u8 = 0x01;
+const RESET_REQUEST_BOOTSEL: u8 = 0x01;
+const RESET_REQUEST_FLASH: u8 = 0x02;
+
+type UsbDriver = Driver<'static, USB>;
+
+// text (or frames) waiting to be sent to the host. Records are kept here from boot until a terminal opens the serial port
+// so this must be large enough for everything logged before that, e.g. while the radio and network start.
+pub(super) static PIPE: Pipe<CriticalSectionRawMutex, 4096> = Pipe::new();
+static HOST_CONNECTED: AtomicBool = AtomicBool::new(false);
+// How long a shell reply waits for room in a pipe before the rest of it is dropped. Nobody may be reading the port (e.g.
+// a terminal that does not set DTR while the logs have filled the pipe) and the shell must not hang waiting for it.
+pub(super) const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+// responses waiting to be sent on the data / command port
+static DATA_PIPE: Pipe<CriticalSectionRawMutex, 1024> = Pipe::new();
+static USB_SINK: UsbSink = UsbSink;
+
+struct UsbSink;
+
+impl Sink for UsbSink {
+    fn name(&self) -> &'static str {
+        "usb"
+    }
+
+    fn write(&self, record: &Record) -> Result<(), SinkError> {
+        let bytes = encode_record(record);
+
+        // only write whole records so that a full pipe never leaves half a line or frame behind
+        if PIPE.free_capacity() < bytes.len() {
+            return Err(SinkError::Full);
+        }
+
+        try_write_all(&PIPE, &bytes);
+        Ok(())
+    }
+}
+
+// With the `log-frames` feature these are replaced by the ones in `frames.rs`, which send binary frames instead of text
+
+#[cfg(not(feature = "log-frames"))]
+fn encode_record(record: &Record) -> heapless::Vec<u8, MAX_RECORD_LEN> {
+    let mut line: String<MAX_RECORD_LEN> = String::new();
+    super::write_line(&mut line, record);
+    line.into_bytes()
+}
+
+#[cfg(not(feature = "log-frames"))]
+fn encode_notice(message: &str) -> heapless::Vec<u8, MAX_RECORD_LEN> {
+    let mut line: String<MAX_RECORD_LEN> = String::new();
+    let _ = write!(line, "{message}\r\n");
+    line.into_bytes()
+}
+
+// sends text to the host regardless of the log filter, used for shell replies
+#[cfg(not(feature = "log-frames"))]
+pub(crate) async fn write_console(text: &str) {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    for line in text.split_inclusive('\n') {
+        if !write_before(&PIPE, line.as_bytes(), deadline).await {
+            break;
+        }
+    }
+}
+
+// A composite usb device with two serial ports and a reset interface. The first serial port carries the log messages
+// and the shell for humans, the second one takes shell commands from host tools using the framed protocol in
+// `command_protocol`. The reset interface lets picotool reboot the board into BOOTSEL.
+pub(crate) fn setup_usb_logging(spawner: &Spawner, driver: UsbDriver) {
+    // picotool only looks for the reset interface on devices with the ids used by the pico sdk
+    let mut config = Config::new(0x2e8a, USB_PRODUCT_ID);
+    config.manufacturer = Some("Embassy");
+    config.product = Some("USB-serial logger");
+    config.serial_number = None;
+    config.max_power = 100;
+    config.max_packet_size_0 = MAX_PACKET_SIZE as u8;
+
+    // needed for a device with more than one serial port
+    config.device_class = 0xef;
+    config.device_sub_class = 0x02;
+    config.device_protocol = 0x01;
+    config.composite_with_iads = true;
+
+    static CONFIG_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static BOS_DESCRIPTOR: StaticCell<[u8; 16]> = StaticCell::new();
+    static MSOS_DESCRIPTOR: StaticCell<[u8; 256]> = StaticCell::new();
+    static CONTROL_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+    static CONSOLE_STATE: StaticCell<State> = StaticCell::new();
+    static DATA_STATE: StaticCell<State> = StaticCell::new();
+    static RESET_HANDLER: StaticCell<ResetHandler> = StaticCell::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        CONFIG_DESCRIPTOR.init([0; 256]),
+        BOS_DESCRIPTOR.init([0; 16]),
+        MSOS_DESCRIPTOR.init([0; 256]),
+        CONTROL_BUF.init([0; 64]),
+    );
+
+    let console = CdcAcmClass::new(
+        &mut builder,
+        CONSOLE_STATE.init(State::new()),
+        MAX_PACKET_SIZE,
+    );
+    let data = CdcAcmClass::new(&mut builder, DATA_STATE.init(State::new()), MAX_PACKET_SIZE);
+    let reset_handler = add_reset_interface(&mut builder);
+    builder.handler(RESET_HANDLER.init(reset_handler));
+
+    let (sender, receiver, control) = console.split_with_control();
+    let (data_sender, data_receiver) = data.split();
+    let device = builder.build();
+
+    add_sink(&USB_SINK, LevelFilter::Trace, Overflow::Drop);
+    spawner
+        .spawn(usb_task(
+            device,
+            sender,
+            receiver,
+            control,
+            data_sender,
+            data_receiver,
+        ))
+        .unwrap();
+}
+
+fn add_reset_interface(builder: &mut Builder<'static, UsbDriver>) -> ResetHandler {
+    // lets windows load its generic usb driver for the interface, picotool needs it
+    builder.msos_descriptor(windows_version::WIN8_1, 0);
+
+    let mut function = builder.function(
+        RESET_INTERFACE_CLASS,
+        RESET_INTERFACE_SUB_CLASS,
+        RESET_INTERFACE_PROTOCOL,
+    );
+    function.msos_feature(msos::CompatibleIdFeatureDescriptor::new("WINUSB", ""));
+
+    let mut interface = function.interface();
+    let interface_number = interface.interface_number();
+    interface.alt_setting(
+        RESET_INTERFACE_CLASS,
+        RESET_INTERFACE_SUB_CLASS,
+        RESET_INTERFACE_PROTOCOL,
+        None,
+    );
+
+    ResetHandler { interface_number }
+}
+
+struct ResetHandler {
+    interface_number: InterfaceNumber,
+}
+
+impl Handler for ResetHandler {
+    fn control_out(&mut self, req: Request, _data: &[u8]) -> Option<OutResponse> {
+        if !matches!(req.request_type, RequestType::Class | RequestType::Vendor)
+            || req.recipient != Recipient::Interface
+            || req.index != u8::from(self.interface_number) as u16
+        {
+            return None;
+        }
+
+        // the reboot happens after a delay so the request is still acknowledged.
+        // The activity led and interface mask that picotool can send in `value` are ignored.
+        let reboot_type = match req.request {
+            RESET_REQUEST_BOOTSEL => REBOOT_TYPE_BOOTSEL,
+            RESET_REQUEST_FLASH => REBOOT_TYPE_NORMAL,
+            _ => return Some(OutResponse::Rejected),
+        };
+
+        reboot(reboot_type, 100);
+        Some(OutResponse::Accepted)
+    }
+}
+
+// Waits until a terminal has opened the usb serial port, returns false if that did not happen within `timeout`.
+// Nothing logged before then is lost so this is only needed to hold off work until somebody is watching.
+pub async fn wait_for_host(timeout: Duration) -> bool {
+    with_timeout(timeout, async {
+        while !HOST_CONNECTED.load(Ordering::Relaxed) {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+// sends a response on the data / command port
+pub(crate) async fn write_data(text: &str) {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    for line in text.split_inclusive('\n') {
+        if !write_before(&DATA_PIPE, line.as_bytes(), deadline).await {
+            break;
+        }
+    }
+}
+
+// Writes `bytes` as a whole once the pipe has room for them, returns false if that did not happen before `deadline`
+pub(super) async fn write_before<const N: usize>(
+    pipe: &Pipe<CriticalSectionRawMutex, N>,
+    bytes: &[u8],
+    deadline: Instant,
+) -> bool {
+    while pipe.free_capacity() < bytes.len() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        Timer::after_millis(10).await;
+    }
+
+    try_write_all(pipe, bytes);
+    true
+}
+
+fn try_write_all<const N: usize>(pipe: &Pipe<CriticalSectionRawMutex, N>, bytes: &[u8]) {
+    // the pipe cannot write across the wraparound of its ring buffer in one go so try a second time
+    if let Ok(n) = pipe.try_write(bytes) {
+        if n < bytes.len() {
+            let _ = pipe.try_write(&bytes[n..]);
+        }
+    }
+}
+
+#[embassy_executor::task]
+async fn usb_task(
+    mut device: UsbDevice<'static, UsbDriver>,
+    mut sender: Sender<'static, UsbDriver>,
+    mut receiver: Receiver<'static, UsbDriver>,
+    control: ControlChanged<'static>,
+    mut data_sender: Sender<'static, UsbDriver>,
+    mut data_receiver: Receiver<'static, UsbDriver>,
+) -> ! {
+    join(
+        device.run(),
+        join4(
+            send(&mut sender, &control),
+            receive(&mut receiver),
+            send_data(&mut data_sender),
+            receive_data(&mut data_receiver),
+        ),
+    )
+    .await;
+    unreachable!()
+}
+
+async fn send(sender: &mut Sender<'static, UsbDriver>, control: &ControlChanged<'static>) -> ! {
+    let mut buf = [0; MAX_PACKET_SIZE as usize];
+    loop {
+        sender.wait_connection().await;
+
+        // the host throws away anything sent before a terminal has opened the port (signalled by DTR),
+        // so leave it in the pipe until then
+        while !sender.dtr() {
+            control.control_changed().await;
+        }
+
+        HOST_CONNECTED.store(true, Ordering::Relaxed);
+        report_dropped();
+
+        loop {
+            let len = match select(PIPE.read(&mut buf), control.control_changed()).await {
+                Either::First(len) => len,
+                Either::Second(()) if sender.dtr() => continue,
+                Either::Second(()) => break,
+            };
+
+            if sender.write_packet(&buf[..len]).await.is_err() {
+                break;
+            }
+
+            // a full packet must be followed by a zero length packet to end the transfer
+            if len == buf.len() && sender.write_packet(&[]).await.is_err() {
+                break;
+            }
+        }
+
+        HOST_CONNECTED.store(false, Ordering::Relaxed);
+    }
+}
+
+// tells whoever just connected that the pipe overflowed while nobody was listening
+fn report_dropped() {
+    let dropped = sink_info()
+        .find(|sink| sink.name == USB_SINK.name())
+        .map_or(0, |sink| sink.dropped);
+
+    if dropped > 0 {
+        let mut message: String<64> = String::new();
+        let _ = write!(
+            message,
+            "{dropped} log records dropped so far, usb buffer full"
+        );
+        try_write_all(&PIPE, &encode_notice(&message));
+    }
+}
+
+// host tools do not necessarily set DTR so responses are sent whenever the port is enabled
+async fn send_data(sender: &mut Sender<'static, UsbDriver>) -> ! {
+    let mut buf = [0; MAX_PACKET_SIZE as usize];
+    loop {
+        sender.wait_connection().await;
+        loop {
+            let len = DATA_PIPE.read(&mut buf).await;
+            if sender.write_packet(&buf[..len]).await.is_err() {
+                break;
+            }
+
+            if len == buf.len() && sender.write_packet(&[]).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+async fn receive(receiver: &mut Receiver<'static, UsbDriver>) -> ! {
+    let mut buf = [0; MAX_PACKET_SIZE as usize];
+    let mut line: LineBuffer<MAX_LINE_LEN> = LineBuffer::new();
+    loop {
+        receiver.wait_connection().await;
+        while let Ok(len) = receiver.read_packet(&mut buf).await {
+            handle_data(&mut line, &buf[..len]);
+        }
+        line.clear();
+    }
+}
+
+fn handle_data(line: &mut LineBuffer<MAX_LINE_LEN>, data: &[u8]) {
+    line.feed(data, |result| match result {
+        Ok(text) => {
+            let text = text.trim();
+            if text == ELF2UF2_TERM {
+                reboot_to_bootsel();
+            } else if !shell::submit(text) {
+                warn!("shell busy, ignoring '{}'", text);
+            }
+        }
+        Err(LineError::TooLong) => {
+            warn!("line longer than {} bytes ignored", MAX_LINE_LEN)
+        }
+    });
+
+    // elf2uf2-term does not necessarily end its message with a newline
+    if line.pending() == ELF2UF2_TERM.as_bytes() {
+        reboot_to_bootsel();
+    }
+}
+
+async fn receive_data(receiver: &mut Receiver<'static, UsbDriver>) -> ! {
+    let mut buf = [0; MAX_PACKET_SIZE as usize];
+    let mut line: LineBuffer<MAX_LINE_LEN> = LineBuffer::new();
+    loop {
+        receiver.wait_connection().await;
+        while let Ok(len) = receiver.read_packet(&mut buf).await {
+            line.feed(&buf[..len], |result| match result {
+                Ok(text) if text.trim().is_empty() => {}
+                Ok(text) => match command_protocol::parse_request(text) {
+                    Ok(request) if !shell::submit_request(request.id, request.command) => {
+                        respond_error(Some(request.id), "busy")
+                    }
+                    Ok(_) => {}
+                    Err(e @ RequestError::MissingCommand(id)) => {
+                        respond_error(Some(id), e.as_str())
+                    }
+                    Err(e) => respond_error(None, e.as_str()),
+                },
+                Err(LineError::TooLong) => respond_error(None, "request too long"),
+            });
+        }
+        line.clear();
+    }
+}
+
+// answers a request that never reached the shell, dropped if there is no room
+fn respond_error(id: Option<u32>, message: &str) {
+    let mut response: String<MAX_RECORD_LEN> = String::new();
+    command_protocol::write_response(&mut response, id, "", Err(message));
+    if DATA_PIPE.free_capacity() >= response.len() {
+        try_write_all(&DATA_PIPE, response.as_bytes());
+    }
+}
+
+fn reboot_to_bootsel() {
+    reboot(REBOOT_TYPE_BOOTSEL, 100);
+}
@@ -1,27 +1,76 @@
-use cyw43::{Control, JoinOptions, NetDriver};
+use core::{cell::Cell, fmt::Write};
+
+use cyw43::{ControlError, JoinOptions, NetDriver};
 use embassy_executor::Spawner;
 use embassy_net::{
+    icmp::{
+        self,
+        ping::{PingManager, PingParams},
+    },
     udp::{PacketMetadata, UdpSocket},
-    Ipv4Address, StackResources,
+    Ipv4Address, Stack, StackResources,
 };
 use embassy_rp::clocks::RoscRng;
-use log::info;
+use embassy_sync::blocking_mutex::ThreadModeMutex;
+use embassy_time::{Duration, Timer};
 use static_cell::StaticCell;
 
+use crate::{
+    dhcp_server::{run_dhcp_server, DhcpServerConfig},
+    provisioning::saved_credentials,
+    radio::{self, SharedControl, POWER_MODES},
+    wifi_credentials::{
+        WifiCredentials, JOIN_STATUS_FAIL, JOIN_STATUS_NO_NETWORKS, JOIN_STATUS_TIMEOUT,
+        MAX_SSID_LEN,
+    },
+};
+
+// dhcp (client or server), dns, the application's udp socket, syslog and the ping of `measure_latency`
+const MAX_SOCKETS: usize = 5;
+const LATENCY_PINGS: u16 = 5;
+
+// the stack handle is not `Sync` so it can only be shared with code running in thread mode (e.g. the serial shell)
+static STACK: ThreadModeMutex<Cell<Option<Stack<'static>>>> = ThreadModeMutex::new(Cell::new(None));
+
 #[embassy_executor::task]
 async fn net_task(mut runner: embassy_net::Runner<'static, cyw43::NetDriver<'static>>) -> ! {
     runner.run().await
 }
 
+#[embassy_executor::task]
+async fn dhcp_server_task(stack: Stack<'static>, config: DhcpServerConfig) -> ! {
+    run_dhcp_server(stack, config).await
+}
+
+// Joins the wifi network from `wifi_credentials` and returns a udp socket bound to `local_port`
 pub async fn setup_network(
     spawner: &Spawner,
     net_device: NetDriver<'static>,
-    control: &mut Control<'static>,
+    control: &SharedControl,
     local_ip: Option<Ipv4Address>,
     local_port: u16,
 ) -> UdpSocket<'static> {
-    let mut rng = RoscRng;
+    let credentials = wifi_credentials();
+    setup_network_with(
+        spawner,
+        net_device,
+        control,
+        &credentials,
+        local_ip,
+        local_port,
+    )
+    .await
+}
 
+// Like `setup_network` but joins the network of `credentials`, e.g. the one of another board's `AccessPoint`
+pub async fn setup_network_with(
+    spawner: &Spawner,
+    net_device: NetDriver<'static>,
+    control: &SharedControl,
+    credentials: &WifiCredentials,
+    local_ip: Option<Ipv4Address>,
+    local_port: u16,
+) -> UdpSocket<'static> {
     // OPTIONAL: speed up connecting to the network once you know your ip address (via DHCP) by putting your address in LOCAL_IP.txt
     let config = match local_ip {
         Some(address) => {
@@ -38,39 +87,36 @@ pub async fn setup_network(
         }
     };
 
-    // Generate random seed
-    let seed = rng.next_u64();
-
-    // Init network stack
-    static RESOURCES: StaticCell<StackResources<3>> = StaticCell::new();
-    let (stack, runner) = embassy_net::new(
-        net_device,
-        config,
-        RESOURCES.init(StackResources::new()),
-        seed,
-    );
-
-    spawner.spawn(net_task(runner)).unwrap();
-
-    // make sure these files exist in your `src` folder
-    let wifi_ssid: &str = include_str!("./WIFI_SSID.txt");
-    let wifi_password: &str = include_str!("./WIFI_PASSWORD.txt");
+    let stack = start_stack(spawner, net_device, config);
 
-    info!("connecting to wifi network '{}'", wifi_ssid);
+    info!("connecting to wifi network '{}'", credentials.ssid());
 
+    #[cfg(feature = "join-diagnostic")]
+    let mut diagnosed = false;
     loop {
-        let options = JoinOptions::new(wifi_password.as_bytes());
-        match control.join(wifi_ssid, options).await {
+        match join(control, credentials).await {
             Ok(_) => {
                 info!("connected to wifi network");
                 break;
             }
-            Err(err) => match err.status {
-                1 => info!("connection attempt failed (generic failure), retrying..."),
-                2 => info!("no matching SSID found (out of range / SSID not in scan), retrying..."),
-                3 => info!("authentication failed (bad password / credentials), retrying..."),
-                _ => info!("unknown status code, retrying..."),
-            },
+            Err(err) => {
+                match err.status {
+                    JOIN_STATUS_FAIL => info!(
+                        "connection attempt failed (generic failure / bad password), retrying..."
+                    ),
+                    JOIN_STATUS_TIMEOUT => info!("connection attempt timed out, retrying..."),
+                    JOIN_STATUS_NO_NETWORKS => info!(
+                        "no matching SSID found (out of range / SSID not in scan), retrying..."
+                    ),
+                    _ => info!("unknown status code, retrying..."),
+                }
+
+                #[cfg(feature = "join-diagnostic")]
+                if err.status == JOIN_STATUS_NO_NETWORKS && !diagnosed {
+                    diagnose_join(control, credentials.ssid()).await;
+                    diagnosed = true;
+                }
+            }
         }
     }
 
@@ -78,6 +124,165 @@ pub async fn setup_network(
     stack.wait_config_up().await;
     info!("config up with {:?}", stack.config_v4());
 
+    bind_socket(stack, local_port)
+}
+
+// the address of the board in access point mode unless `AccessPoint::dhcp` is changed
+pub const AP_ADDRESS: Ipv4Address = Ipv4Address::new(192, 168, 4, 1);
+// the 2.4GHz channels allowed in most countries
+const MIN_AP_CHANNEL: u8 = 1;
+const MAX_AP_CHANNEL: u8 = 11;
+const DEFAULT_AP_CHANNEL: u8 = 6;
+
+const AP_SSID: &str = match option_env!("PICO_AP_SSID") {
+    Some(ssid) => ssid,
+    None => "",
+};
+const AP_PASSWORD: &str = match option_env!("PICO_AP_PASSWORD") {
+    Some(password) => password,
+    None => "",
+};
+const AP_CHANNEL: u8 = match option_env!("PICO_AP_CHANNEL") {
+    Some(channel) => match parse_channel(channel) {
+        Some(channel) => channel,
+        None => panic!("PICO_AP_CHANNEL must be a channel from 1 to 11"),
+    },
+    None => DEFAULT_AP_CHANNEL,
+};
+
+// bad settings fail the build rather than every boot
+const _: () = assert!(
+    AP_SSID.len() <= MAX_SSID_LEN,
+    "PICO_AP_SSID must be at most 32 bytes"
+);
+// the radio only takes a passphrase for the access point, not a pre-shared key
+const _: () = assert!(
+    AP_PASSWORD.is_empty() || (AP_PASSWORD.len() >= 8 && AP_PASSWORD.len() <= 63),
+    "PICO_AP_PASSWORD must be empty or 8 to 63 characters"
+);
+
+const fn parse_channel(channel: &str) -> Option<u8> {
+    let digits = channel.as_bytes();
+    if digits.is_empty() || digits.len() > 2 {
+        return None;
+    }
+    let mut value = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        if !digits[i].is_ascii_digit() {
+            return None;
+        }
+        value = value * 10 + (digits[i] - b'0');
+        i += 1;
+    }
+    if value < MIN_AP_CHANNEL || value > MAX_AP_CHANNEL {
+        return None;
+    }
+    Some(value)
+}
+
+// a network hosted by the board, an empty password makes it an open network
+pub struct AccessPoint {
+    pub credentials: WifiCredentials,
+    pub channel: u8,
+    // the board takes the server address, clients get the addresses of the pool
+    pub dhcp: DhcpServerConfig,
+}
+
+// Starts an access point instead of joining a network, so boards can talk to each other without a router. The board
+// runs a DHCP server for the boards that join (see `dhcp_server.rs`).
+pub async fn setup_access_point(
+    spawner: &Spawner,
+    net_device: NetDriver<'static>,
+    control: &SharedControl,
+    access_point: &AccessPoint,
+    local_port: u16,
+) -> UdpSocket<'static> {
+    let dhcp = &access_point.dhcp;
+    let prefix_len = u32::from(dhcp.options.subnet_mask).count_ones() as u8;
+    let config = embassy_net::Config::ipv4_static(embassy_net::StaticConfigV4 {
+        address: embassy_net::Ipv4Cidr::new(dhcp.options.server, prefix_len),
+        dns_servers: heapless::Vec::new(),
+        gateway: None,
+    });
+    let stack = start_stack(spawner, net_device, config);
+
+    let ssid = access_point.credentials.ssid();
+    let password = access_point.credentials.password();
+    info!(
+        "starting {} access point '{}' on channel {}",
+        if password.is_empty() { "open" } else { "wpa2" },
+        ssid,
+        access_point.channel
+    );
+    {
+        let mut control = control.lock().await;
+        if password.is_empty() {
+            control.start_ap_open(ssid, access_point.channel).await;
+        } else {
+            control
+                .start_ap_wpa2(ssid, password, access_point.channel)
+                .await;
+        }
+    }
+
+    spawner
+        .spawn(dhcp_server_task(stack, dhcp.clone()))
+        .unwrap();
+    info!("access point up with address {}", dhcp.options.server);
+
+    bind_socket(stack, local_port)
+}
+
+// The access point from `PICO_AP_SSID`, `PICO_AP_PASSWORD` and `PICO_AP_CHANNEL` when the firmware was built, `None`
+// if `PICO_AP_SSID` is empty
+pub fn access_point() -> Option<AccessPoint> {
+    if AP_SSID.is_empty() {
+        return None;
+    }
+
+    // checked when the firmware is built, so this does not fail
+    let credentials = match WifiCredentials::new(AP_SSID, AP_PASSWORD) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            error!("invalid access point settings: {}", e.as_str());
+            return None;
+        }
+    };
+
+    Some(AccessPoint {
+        credentials,
+        channel: AP_CHANNEL,
+        dhcp: DhcpServerConfig::new(AP_ADDRESS),
+    })
+}
+
+fn start_stack(
+    spawner: &Spawner,
+    net_device: NetDriver<'static>,
+    config: embassy_net::Config,
+) -> Stack<'static> {
+    let mut rng = RoscRng;
+
+    // Generate random seed
+    let seed = rng.next_u64();
+
+    // Init network stack
+    static RESOURCES: StaticCell<StackResources<MAX_SOCKETS>> = StaticCell::new();
+    let (stack, runner) = embassy_net::new(
+        net_device,
+        config,
+        RESOURCES.init(StackResources::new()),
+        seed,
+    );
+
+    spawner.spawn(net_task(runner)).unwrap();
+    STACK.lock(|s| s.set(Some(stack)));
+
+    stack
+}
+
+fn bind_socket(stack: Stack<'static>, local_port: u16) -> UdpSocket<'static> {
     static RX_BUFFER: StaticCell<[u8; 4096]> = StaticCell::new();
     static TX_BUFFER: StaticCell<[u8; 4096]> = StaticCell::new();
     static RX_META: StaticCell<[PacketMetadata; 16]> = StaticCell::new();
@@ -92,3 +297,108 @@ pub async fn setup_network(
 
     socket
 }
+
+// Scans for `ssid` once joining found no network of that name (`JOIN_STATUS_NO_NETWORKS`), so the failure comes with
+// what the radio hears
+#[cfg(feature = "join-diagnostic")]
+async fn diagnose_join(control: &SharedControl, ssid: &str) {
+    info!("scanning for wifi network '{}'", ssid);
+    let results = radio::scan(control).await;
+
+    if let Some(network) = results.find(ssid) {
+        info!(
+            "found wifi network '{}' on channel {} at {}dBm",
+            ssid, network.channel, network.rssi
+        );
+        return;
+    }
+
+    match results.closest(ssid) {
+        Some((network, distance)) => warn!(
+            "wifi network '{}' not found, closest of {} networks is '{}' ({} edits away) on channel {} at {}dBm",
+            ssid,
+            results.networks().len(),
+            network.ssid.as_str(),
+            distance,
+            network.channel,
+            network.rssi
+        ),
+        None => warn!("wifi network '{}' not found, no networks in range", ssid),
+    }
+}
+
+// The credentials saved by BLE provisioning (see `provisioning.rs`), otherwise the ones built into the firmware
+pub fn wifi_credentials() -> WifiCredentials {
+    saved_credentials().unwrap_or_else(|| {
+        // make sure these files exist in your `src` folder
+        let wifi_ssid: &str = include_str!("./WIFI_SSID.txt");
+        let wifi_password: &str = include_str!("./WIFI_PASSWORD.txt");
+        WifiCredentials::new(wifi_ssid, wifi_password)
+            .expect("invalid WIFI_SSID.txt or WIFI_PASSWORD.txt")
+    })
+}
+
+pub async fn join(
+    control: &SharedControl,
+    credentials: &WifiCredentials,
+) -> Result<(), ControlError> {
+    let options = if credentials.password().is_empty() {
+        JoinOptions::new_open()
+    } else {
+        let mut options = JoinOptions::new(credentials.password().as_bytes());
+        options.passphrase_is_prehashed = credentials.is_psk();
+        options
+    };
+    control.lock().await.join(credentials.ssid(), options).await
+}
+
+// returns the network stack once `setup_network` has created it
+pub fn stack() -> Option<Stack<'static>> {
+    STACK.lock(|s| s.get())
+}
+
+// Pings `target` (the gateway if `None`) in every wifi power mode and writes the average round trip times to `out`.
+// Power saving modes let the radio sleep between beacons so packets sent to the board wait for it to wake up.
+// The power mode is restored afterwards.
+pub async fn measure_latency(
+    target: Option<Ipv4Address>,
+    out: &mut impl Write,
+) -> Result<(), &'static str> {
+    let stack = stack().ok_or("network not setup")?;
+    let target = target
+        .or_else(|| stack.config_v4().and_then(|config| config.gateway))
+        .ok_or("no gateway, give an address to ping")?;
+
+    let mut rx_meta = [icmp::PacketMetadata::EMPTY; 1];
+    let mut tx_meta = [icmp::PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; 256];
+    let mut tx_buffer = [0; 256];
+    let mut ping = PingManager::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+
+    let mut params = PingParams::new(target);
+    params
+        .set_count(LATENCY_PINGS)
+        .set_timeout(Duration::from_secs(2))
+        .set_rate_limit(Duration::from_millis(200));
+
+    let _ = writeln!(out, "average of {LATENCY_PINGS} pings to {target}:");
+    let original = radio::power_mode();
+    for (name, mode) in POWER_MODES {
+        radio::set_power_mode(*mode).await?;
+        // let the radio settle into the new mode
+        Timer::after(Duration::from_millis(500)).await;
+
+        let _ = match ping.ping(&params).await {
+            Ok(average) => writeln!(out, "{name:<12} {}ms", average.as_millis()),
+            Err(e) => writeln!(out, "{name:<12} failed ({e:?})"),
+        };
+    }
+
+    radio::set_power_mode(original).await
+}
@@ -0,0 +1,139 @@
+use core::panic::PanicInfo;
+
+#[cfg(feature = "rp235x")]
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{exception, ExceptionFrame};
+use embassy_executor::Spawner;
+use embassy_rp::gpio::{AnyPin, Level, Output};
+use embassy_time::{block_for, Duration, Timer};
+
+use crate::{
+    chip::{reboot, REBOOT_TYPE_BOOTSEL, REBOOT_TYPE_NORMAL},
+    crash::{self, FaultRegisters},
+};
+
+// What happens after a panic or hard fault is selected with a cargo feature:
+// (none)          - reboot into the BOOTSEL bootloader, handy on the bench
+// panic-reboot    - reboot and run the firmware again
+// panic-halt      - stop and blink an error pattern on `HALT_LED_PIN`
+// panic-safe-mode - reboot, but after `SAFE_MODE_CRASHES` consecutive crashes stop before the radio is started
+#[cfg(any(
+    all(feature = "panic-reboot", feature = "panic-halt"),
+    all(feature = "panic-reboot", feature = "panic-safe-mode"),
+    all(feature = "panic-halt", feature = "panic-safe-mode"),
+))]
+compile_error!(
+    "only one of the `panic-reboot`, `panic-halt` and `panic-safe-mode` features can be enabled"
+);
+
+pub const SAFE_MODE_CRASHES: u32 = 3;
+
+// the on-board led is driven by the wifi chip which cannot be used after a panic, so connect an led to this pin instead
+const HALT_LED_PIN: u8 = 16;
+
+// once the firmware has been running this long the consecutive crash count is reset
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+#[panic_handler]
+fn core_panic(info: &PanicInfo) -> ! {
+    // keep the panic message so that it can be logged after the reboot
+    crash::record_panic(info);
+    after_crash()
+}
+
+// MemManage, BusFault and UsageFault are not enabled so they escalate to a hard fault
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    crash::record_hard_fault(fault_registers(frame));
+    after_crash()
+}
+
+#[cfg(feature = "rp235x")]
+fn fault_registers(frame: &ExceptionFrame) -> FaultRegisters {
+    let scb = unsafe { &*SCB::PTR };
+    FaultRegisters {
+        pc: frame.pc(),
+        lr: frame.lr(),
+        xpsr: frame.xpsr(),
+        cfsr: scb.cfsr.read(),
+        hfsr: scb.hfsr.read(),
+        mmfar: scb.mmfar.read(),
+        bfar: scb.bfar.read(),
+    }
+}
+
+// the Cortex-M0+ of the RP2040 (ARMv6-M) has no fault status registers so they are left at zero
+#[cfg(feature = "rp2040")]
+fn fault_registers(frame: &ExceptionFrame) -> FaultRegisters {
+    FaultRegisters {
+        pc: frame.pc(),
+        lr: frame.lr(),
+        xpsr: frame.xpsr(),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn after_crash() -> ! {
+    if cfg!(feature = "panic-halt") {
+        halt();
+    }
+
+    let reboot_type = if cfg!(any(feature = "panic-reboot", feature = "panic-safe-mode")) {
+        REBOOT_TYPE_NORMAL
+    } else {
+        REBOOT_TYPE_BOOTSEL
+    };
+
+    reboot(reboot_type, 100);
+
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+fn halt() -> ! {
+    cortex_m::interrupt::disable();
+
+    // nothing else runs from now on so taking the pin cannot conflict with other code
+    let mut led = Output::new(unsafe { AnyPin::steal(HALT_LED_PIN) }, Level::Low);
+
+    // three short flashes followed by a pause
+    loop {
+        for _ in 0..3 {
+            led.set_high();
+            block_for(Duration::from_millis(150));
+            led.set_low();
+            block_for(Duration::from_millis(150));
+        }
+
+        block_for(Duration::from_millis(1000));
+    }
+}
+
+pub fn in_safe_mode() -> bool {
+    cfg!(feature = "panic-safe-mode") && crash::consecutive_crashes() >= SAFE_MODE_CRASHES
+}
+
+pub(crate) fn setup_crash_counter(spawner: &Spawner) {
+    if !in_safe_mode() {
+        spawner.spawn(stable_task()).unwrap();
+    }
+}
+
+// minimal firmware path that keeps logging and the serial shell running but nothing else
+pub(crate) async fn safe_mode() -> ! {
+    warn!(
+        "safe mode: {} consecutive crashes, the radio will not be started",
+        crash::consecutive_crashes()
+    );
+    warn!("type 'crash' to see what happened, then 'crash clear' and 'reboot' to leave safe mode");
+    core::future::pending().await
+}
+
+#[embassy_executor::task]
+async fn stable_task() {
+    Timer::after(STABLE_UPTIME).await;
+    if crash::consecutive_crashes() > 0 {
+        info!("firmware stable, resetting crash count");
+        crash::set_consecutive_crashes(0);
+    }
+}
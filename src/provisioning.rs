@@ -0,0 +1,176 @@
+use core::{mem::MaybeUninit, ptr::addr_of};
+
+use embassy_rp::{
+    flash::{self, Blocking, Flash, ERASE_SIZE, FLASH_BASE},
+    peripherals::FLASH,
+};
+use embassy_time::{Duration, Timer};
+use trouble_host::prelude::{DefaultPacketPool, GattConnection, GattConnectionEvent, GattEvent};
+
+use crate::{
+    ble::{advertise, BlePeripheral},
+    chip::FLASH_SIZE,
+    network::join,
+    provisioning_service::{ProvisioningServer, Status, PROVISIONING_SERVICE_UUID},
+    radio::SharedControl,
+    wifi_credentials::{WifiCredentials, RECORD_LEN},
+};
+
+// Wi-Fi credentials sent from a phone over BLE. They are only saved once the board has joined the network with them,
+// in the last flash sector which is not part of the firmware image (see `.wifi_credentials` in memory-rp235x.x and
+// memory-rp2040.x) so they survive flashing a new firmware. `network::wifi_credentials` prefers them over the ones
+// built in from WIFI_SSID.txt and WIFI_PASSWORD.txt.
+
+pub type BoardFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+
+#[link_section = ".wifi_credentials"]
+static WIFI_CREDENTIALS: MaybeUninit<[u8; RECORD_LEN]> = MaybeUninit::uninit();
+
+// the name the board advertises while it waits for credentials
+const NAME: &str = "pico2w-setup";
+
+// `None` if the board was never provisioned or the credentials were erased
+pub fn saved_credentials() -> Option<WifiCredentials> {
+    // the sector is not written by the firmware image so the compiler cannot know its contents
+    let record = unsafe { addr_of!(WIFI_CREDENTIALS).read_volatile().assume_init() };
+    WifiCredentials::from_record(&record)
+}
+
+pub fn save_credentials(
+    flash: &mut BoardFlash,
+    credentials: &WifiCredentials,
+) -> Result<(), flash::Error> {
+    let offset = sector_offset();
+    flash.blocking_erase(offset, offset + ERASE_SIZE as u32)?;
+    flash.blocking_write(offset, &credentials.to_record())
+}
+
+fn sector_offset() -> u32 {
+    addr_of!(WIFI_CREDENTIALS) as u32 - FLASH_BASE as u32
+}
+
+// Advertises the provisioning service until a phone has sent credentials that join the network, then saves them.
+// The radio stays joined to the network. This can only be called once per boot, see `ProvisioningServer::with_name`.
+pub async fn provision(
+    peripheral: &mut BlePeripheral,
+    control: &SharedControl,
+    flash: &mut BoardFlash,
+) -> WifiCredentials {
+    let server = ProvisioningServer::with_name(NAME).unwrap();
+
+    info!("waiting for wifi credentials over bluetooth");
+    loop {
+        let conn = match advertise(peripheral, NAME, PROVISIONING_SERVICE_UUID).await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("advertising failed: {:?}", e);
+                Timer::after(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let conn = match conn.with_attribute_server(&server) {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("gatt connection failed: {:?}", e);
+                continue;
+            }
+        };
+
+        info!("provisioning client connected");
+        if let Some(credentials) = handle_events(&server, &conn, control, flash).await {
+            return credentials;
+        }
+    }
+}
+
+// returns the credentials once they are saved or `None` when the client disconnects
+async fn handle_events(
+    server: &ProvisioningServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    control: &SharedControl,
+    flash: &mut BoardFlash,
+) -> Option<WifiCredentials> {
+    loop {
+        let connect = match conn.next().await {
+            GattConnectionEvent::Disconnected { reason } => {
+                info!("provisioning client disconnected: {:?}", reason);
+                return None;
+            }
+            GattConnectionEvent::Gatt { event } => {
+                let result = match &event {
+                    GattEvent::Write(write) => server.check_write(write.handle(), write.data()),
+                    _ => Ok(false),
+                };
+
+                let (connect, reply) = match result {
+                    Ok(connect) => (connect, event.accept()),
+                    Err(e) => {
+                        warn!("rejected write: {:?}", e);
+                        (false, event.reject(e))
+                    }
+                };
+
+                match reply {
+                    Ok(reply) => reply.send().await,
+                    Err(e) => error!("gatt reply error: {:?}", e),
+                }
+                connect
+            }
+            _ => false,
+        };
+
+        if !connect {
+            continue;
+        }
+
+        let status = match try_credentials(server, conn, control, flash).await {
+            Ok(credentials) => {
+                info!(
+                    "saved credentials for wifi network '{}'",
+                    credentials.ssid()
+                );
+                notify(server, conn, Status::Provisioned).await;
+                return Some(credentials);
+            }
+            Err(status) => status,
+        };
+        notify(server, conn, status).await;
+    }
+}
+
+async fn try_credentials(
+    server: &ProvisioningServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    control: &SharedControl,
+    flash: &mut BoardFlash,
+) -> Result<WifiCredentials, Status> {
+    let credentials = server.credentials().map_err(|e| {
+        warn!("invalid credentials: {}", e);
+        Status::InvalidCredentials
+    })?;
+
+    info!("joining wifi network '{}'", credentials.ssid());
+    notify(server, conn, Status::Connecting).await;
+    join(control, &credentials).await.map_err(|e| {
+        warn!("joining failed with status {}", e.status);
+        Status::from_join_status(e.status)
+    })?;
+
+    save_credentials(flash, &credentials).map_err(|e| {
+        error!("saving credentials failed: {:?}", e);
+        Status::SaveFailed
+    })?;
+
+    Ok(credentials)
+}
+
+async fn notify(
+    server: &ProvisioningServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    status: Status,
+) {
+    if let Err(e) = server.notify_status(conn, status).await {
+        error!("status notify error: {:?}", e);
+    }
+}
@@ -1,17 +1,73 @@
-use cyw43::{Control, NetDriver};
+use core::cell::Cell;
+
+use cyw43::{
+    bluetooth::BtDriver, Control, NetDriver, PowerManagementMode, ScanOptions, ScanType,
+    SpiBusCyw43,
+};
 use cyw43_pio::PioSpi;
-use embassy_executor::Spawner;
-use embassy_rp::{gpio::Output, peripherals::PIO0};
-use log::info;
+use embassy_executor::{SpawnToken, Spawner};
+use embassy_rp::{
+    gpio::Output,
+    peripherals::{DMA_CH0, PIO0},
+};
+use embassy_sync::{
+    blocking_mutex::{raw::NoopRawMutex, ThreadModeMutex},
+    mutex::Mutex,
+};
 use static_cell::StaticCell;
 
-type Cyw43Spi = PioSpi<'static, PIO0, 0, embassy_rp::peripherals::DMA_CH0>;
+use crate::{
+    panic_policy::{in_safe_mode, safe_mode},
+    wifi_scan::{ScanResults, ScannedNetwork},
+};
+
+// the spi bus to the radio on a PIO block, state machine and DMA channel
+pub type RadioSpi<PIO, const SM: usize, DMA> = PioSpi<'static, PIO, SM, DMA>;
+pub type Runner<SPI> = cyw43::Runner<'static, Output<'static>, SPI>;
+
+// the radio control is shared so that the serial shell can use it (e.g. to drive the led) alongside the application
+pub type SharedControl = Mutex<NoopRawMutex, Control<'static>>;
+
+static CONTROL: ThreadModeMutex<Cell<Option<&'static SharedControl>>> =
+    ThreadModeMutex::new(Cell::new(None));
+static POWER_MODE: ThreadModeMutex<Cell<PowerManagementMode>> =
+    ThreadModeMutex::new(Cell::new(PowerManagementMode::PowerSave));
+
+// the power mode used by `PicoW2Board`, e.g. `PICO_WIFI_POWER="performance"` (see `.cargo/config.toml`)
+const DEFAULT_POWER_MODE: &str = match option_env!("PICO_WIFI_POWER") {
+    Some(mode) => mode,
+    None => "powersave",
+};
+
+// The names used by the `wifi-power` shell command, from the least to the most power saving.
+// Power saving lets the radio sleep between beacons from the access point, which delays incoming packets.
+pub const POWER_MODES: &[(&str, PowerManagementMode)] = &[
+    ("none", PowerManagementMode::None),
+    ("performance", PowerManagementMode::Performance),
+    ("powersave", PowerManagementMode::PowerSave),
+    ("throughput", PowerManagementMode::ThroughputThrottling),
+    ("aggressive", PowerManagementMode::Aggressive),
+    ("supersave", PowerManagementMode::SuperSave),
+];
 
-#[embassy_executor::task]
-async fn wifi_task(runner: cyw43::Runner<'static, Output<'static>, Cyw43Spi>) -> ! {
-    runner.run().await
+// Declares the task that runs the radio driver for the spi bus on a PIO block, state machine and DMA channel.
+// Embassy tasks cannot be generic so every other combination needs its own task, e.g. to leave PIO0 free:
+// `wifi_task!(wifi_task, PIO1, 0, DMA_CH1);` and then `setup_radio(&spawner, pwr, spi, wifi_task, power_mode)`
+#[macro_export]
+macro_rules! wifi_task {
+    ($vis:vis $name:ident, $pio:ty, $sm:literal, $dma:ty) => {
+        #[embassy_executor::task]
+        $vis async fn $name(
+            runner: $crate::radio::Runner<$crate::radio::RadioSpi<$pio, $sm, $dma>>,
+        ) -> ! {
+            runner.run().await
+        }
+    };
 }
 
+// the wiring used by the examples
+wifi_task!(pub wifi_task, PIO0, 0, DMA_CH0);
+
 // by putting this in a known location every time the bootloader can detect it (via MD5 hashing) and not have to reflash this
 #[link_section = ".modem_firmware"]
 static MODEM_FIRMWARE: &[u8] = include_bytes!("../cyw43-firmware/43439A0.bin");
@@ -20,27 +76,150 @@ static MODEM_FIRMWARE: &[u8] = include_bytes!("../cyw43-firmware/43439A0.bin");
 #[link_section = ".modem_firmware"]
 static COUNTRY_LOCALE_MATRIX: &[u8] = include_bytes!("../cyw43-firmware/43439A0_clm.bin");
 
-pub async fn setup_radio(
+// only linked into firmware that uses `setup_radio_with_bluetooth`
+#[link_section = ".modem_firmware"]
+static BLUETOOTH_FIRMWARE: &[u8] = include_bytes!("../cyw43-firmware/43439A0_btfw.bin");
+
+static STATE: StaticCell<cyw43::State> = StaticCell::new();
+
+// `wifi_task` must match the PIO block, state machine and DMA channel of `spi`, see `wifi_task!`
+pub async fn setup_radio<SPI: SpiBusCyw43 + 'static, S>(
     spawner: &Spawner,
     pwr: Output<'static>,
-    spi: Cyw43Spi,
-) -> (NetDriver<'static>, Control<'static>) {
+    spi: SPI,
+    wifi_task: fn(Runner<SPI>) -> SpawnToken<S>,
+    power_mode: PowerManagementMode,
+) -> (NetDriver<'static>, &'static SharedControl) {
+    // after repeated crashes the radio (and everything that depends on it) is not started
+    if in_safe_mode() {
+        safe_mode().await;
+    }
+
     // setup network buffers and init the modem
-    static STATE: StaticCell<cyw43::State> = StaticCell::new();
     let state = STATE.init(cyw43::State::new());
-    let (net_device, mut control, runner) = cyw43::new(state, pwr, spi, MODEM_FIRMWARE).await;
+    let (net_device, control, runner) = cyw43::new(state, pwr, spi, MODEM_FIRMWARE).await;
+    let control = start_radio(spawner, control, runner, wifi_task, power_mode).await;
+
+    (net_device, control)
+}
+
+// Like `setup_radio` but also starts the bluetooth controller. It shares the spi bus with wifi and the returned driver
+// is the HCI transport for the BLE host stack, see `ble.rs`.
+pub async fn setup_radio_with_bluetooth<SPI: SpiBusCyw43 + 'static, S>(
+    spawner: &Spawner,
+    pwr: Output<'static>,
+    spi: SPI,
+    wifi_task: fn(Runner<SPI>) -> SpawnToken<S>,
+    power_mode: PowerManagementMode,
+) -> (
+    NetDriver<'static>,
+    BtDriver<'static>,
+    &'static SharedControl,
+) {
+    if in_safe_mode() {
+        safe_mode().await;
+    }
+
+    let state = STATE.init(cyw43::State::new());
+    let (net_device, bt_device, control, runner) =
+        cyw43::new_with_bluetooth(state, pwr, spi, MODEM_FIRMWARE, BLUETOOTH_FIRMWARE).await;
+    let control = start_radio(spawner, control, runner, wifi_task, power_mode).await;
+
+    (net_device, bt_device, control)
+}
 
+async fn start_radio<SPI: SpiBusCyw43 + 'static, S>(
+    spawner: &Spawner,
+    mut control: Control<'static>,
+    runner: Runner<SPI>,
+    wifi_task: fn(Runner<SPI>) -> SpawnToken<S>,
+    power_mode: PowerManagementMode,
+) -> &'static SharedControl {
     // run the wifi runtime on an async task
     spawner.spawn(wifi_task(runner)).unwrap();
 
     // set the country locale matrix and power management
     // wifi_task MUST be running before this gets called
     control.init(COUNTRY_LOCALE_MATRIX).await;
-    control
-        .set_power_management(cyw43::PowerManagementMode::PowerSave)
-        .await;
+    control.set_power_management(power_mode).await;
+    POWER_MODE.lock(|m| m.set(power_mode));
 
     info!("wifi module setup complete");
 
-    (net_device, control)
+    static SHARED_CONTROL: StaticCell<SharedControl> = StaticCell::new();
+    let control = &*SHARED_CONTROL.init(Mutex::new(control));
+    CONTROL.lock(|c| c.set(Some(control)));
+
+    control
+}
+
+// returns the radio control once `setup_radio` has completed
+pub fn control() -> Option<&'static SharedControl> {
+    CONTROL.lock(|c| c.get())
+}
+
+pub fn power_mode() -> PowerManagementMode {
+    POWER_MODE.lock(|m| m.get())
+}
+
+// switches the power mode at runtime, e.g. `wifi-power performance` for a quicker response to incoming packets
+pub async fn set_power_mode(mode: PowerManagementMode) -> Result<(), &'static str> {
+    let control = control().ok_or("radio not setup")?;
+    control.lock().await.set_power_management(mode).await;
+    POWER_MODE.lock(|m| m.set(mode));
+    Ok(())
+}
+
+// Lists the networks the radio can hear on all channels, which takes a few seconds. The radio keeps its connection.
+pub async fn scan(control: &SharedControl) -> ScanResults {
+    let mut options = ScanOptions::default();
+    // probing instead of waiting for beacons is quicker
+    options.scan_type = ScanType::Active;
+
+    let mut control = control.lock().await;
+    let mut scanner = control.scan(options).await;
+    let mut results = ScanResults::new();
+    while let Some(bss) = scanner.next().await {
+        let ssid = &bss.ssid[..(bss.ssid_len as usize).min(bss.ssid.len())];
+        // the low byte of the channel spec is the channel number on 2.4GHz
+        let channel = if bss.ctl_ch != 0 {
+            bss.ctl_ch
+        } else {
+            bss.chanspec as u8
+        };
+        results.add(ScannedNetwork::new(
+            ssid,
+            bss.bssid,
+            channel,
+            bss.rssi,
+            bss.capability,
+        ));
+    }
+
+    results
+}
+
+pub fn parse_power_mode(name: &str) -> Option<PowerManagementMode> {
+    POWER_MODES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, mode)| *mode)
+}
+
+pub fn power_mode_name(mode: PowerManagementMode) -> &'static str {
+    POWER_MODES
+        .iter()
+        .find(|(_, m)| *m == mode)
+        .map_or("unknown", |(name, _)| name)
+}
+
+// the power mode selected with `PICO_WIFI_POWER` when the firmware was built
+pub fn default_power_mode() -> PowerManagementMode {
+    parse_power_mode(DEFAULT_POWER_MODE).unwrap_or_else(|| {
+        warn!(
+            "invalid PICO_WIFI_POWER '{}', using powersave",
+            DEFAULT_POWER_MODE
+        );
+        PowerManagementMode::PowerSave
+    })
 }
@@ -0,0 +1,448 @@
+use core::{
+    cell::{Cell, RefCell},
+    fmt::Write,
+};
+
+use embassy_executor::Spawner;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, Mutex},
+    channel::Channel,
+};
+use embassy_time::{Duration, Instant, Timer};
+use heapless::{String, Vec};
+use log::LevelFilter;
+
+use crate::{
+    chip::{reboot, REBOOT_TYPE_BOOTSEL, REBOOT_TYPE_NORMAL},
+    command_protocol, crash,
+    logging::{
+        self, ring,
+        usb::{write_console, write_data},
+        Overflow,
+    },
+    network, panic_policy, radio,
+};
+
+pub const MAX_LINE_LEN: usize = 128;
+const MAX_COMMANDS: usize = 16;
+// large enough for the output of `log-dump`
+const MAX_REPLY_LEN: usize = 2048;
+// the reply plus the request id in front of every line
+const MAX_RESPONSE_LEN: usize = MAX_REPLY_LEN + 512;
+
+pub type Line = String<MAX_LINE_LEN>;
+pub type Args<'a> = core::str::SplitAsciiWhitespace<'a>;
+
+// a command that an example binary can add to the shell with `register`
+pub struct Command {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub run: fn(args: Args<'_>, out: &mut dyn Write) -> Result<(), &'static str>,
+}
+
+const BUILTIN_COMMANDS: &[(&str, &str)] = &[
+    ("help", "list available commands"),
+    (
+        "status",
+        "show uptime, log filter and radio / network state",
+    ),
+    ("led on|off", "switch the on-board led"),
+    ("reboot", "restart the firmware"),
+    ("bootsel", "reboot into the BOOTSEL bootloader"),
+    (
+        "log-level [filter]",
+        "show or set the log filter, e.g. 'info,rp_pico2w_examples::network=debug'",
+    ),
+    (
+        "log-sink [name level]",
+        "list log sinks or set the level of one, e.g. 'log-sink syslog warn'",
+    ),
+    ("log-dump", "show the most recent log output"),
+    ("net", "show network configuration"),
+    (
+        "wifi-power [mode]",
+        "show or set the wifi power mode: none, performance, powersave, throughput, aggressive or supersave",
+    ),
+    (
+        "wifi-latency [ip]",
+        "ping the gateway (or ip) in every wifi power mode and show the round trip times",
+    ),
+    (
+        "wifi-scan",
+        "list the wifi networks the board can hear, strongest first",
+    ),
+    (
+        "crash [clear]",
+        "show or clear the report of the last crash",
+    ),
+];
+
+static COMMANDS: Mutex<CriticalSectionRawMutex, RefCell<Vec<&'static Command, MAX_COMMANDS>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+// where a line came from and where the reply goes
+#[derive(Clone, Copy)]
+enum Origin {
+    // the log port, replies are plain text for humans
+    Console,
+    // the data / command port, replies are framed with the request id (see `command_protocol`)
+    Data(u32),
+}
+
+// lines received from the usb serial ports waiting to be executed
+static LINES: Channel<CriticalSectionRawMutex, (Origin, Line), 2> = Channel::new();
+
+// set by `reboot` and `bootsel`, the reboot happens once the reply has been sent
+static PENDING_REBOOT: Mutex<CriticalSectionRawMutex, Cell<Option<u32>>> =
+    Mutex::new(Cell::new(None));
+
+pub fn register(command: &'static Command) {
+    COMMANDS.lock(|commands| {
+        if commands.borrow_mut().push(command).is_err() {
+            warn!(
+                "shell command '{}' not registered, too many commands",
+                command.name
+            );
+        }
+    });
+}
+
+pub fn setup_shell(spawner: &Spawner) {
+    spawner.spawn(shell_task()).unwrap();
+}
+
+// queues a line for the shell task, returns false if the shell is still busy
+pub fn submit(line: &str) -> bool {
+    enqueue(Origin::Console, line)
+}
+
+// queues a command received on the data port, the response is framed with `id`
+pub(crate) fn submit_request(id: u32, command: &str) -> bool {
+    enqueue(Origin::Data(id), command)
+}
+
+fn enqueue(origin: Origin, line: &str) -> bool {
+    let mut buf = Line::new();
+    if buf.push_str(line).is_err() {
+        return false;
+    }
+
+    LINES.try_send((origin, buf)).is_ok()
+}
+
+#[embassy_executor::task]
+async fn shell_task() -> ! {
+    loop {
+        let (origin, line) = LINES.receive().await;
+        match origin {
+            Origin::Console => {
+                let mut reply = Reply::new();
+                execute(&line, &mut reply).await;
+                write_console(&reply.text).await;
+            }
+            Origin::Data(id) => {
+                let mut output: String<MAX_REPLY_LEN> = String::new();
+                let result = run(&line, &mut output).await;
+                let mut response: String<MAX_RESPONSE_LEN> = String::new();
+                command_protocol::write_response(&mut response, Some(id), &output, result);
+                write_data(&response).await;
+            }
+        }
+
+        if let Some(reboot_type) = PENDING_REBOOT.lock(Cell::take) {
+            // give the usb task a chance to send the reply before the usb device disappears
+            Timer::after(Duration::from_millis(100)).await;
+            reboot(reboot_type, 100);
+        }
+    }
+}
+
+// runs a command line and writes its output (and error if any) to `out`
+pub async fn execute(line: &str, out: &mut impl Write) {
+    if let Err(e) = run(line, out).await {
+        let _ = writeln!(out, "error: {e}");
+    }
+}
+
+// runs a command line and writes its output to `out`
+pub async fn run(line: &str, out: &mut impl Write) -> Result<(), &'static str> {
+    let mut args = line.split_ascii_whitespace();
+    let Some(name) = args.next() else {
+        return Ok(());
+    };
+
+    match name {
+        "help" => help(out),
+        "status" => status(out),
+        "led" => led(args, out).await,
+        "reboot" => restart(REBOOT_TYPE_NORMAL, out),
+        "bootsel" => restart(REBOOT_TYPE_BOOTSEL, out),
+        "log-level" => log_level(args, out),
+        "log-sink" => log_sink(args, out),
+        "log-dump" => {
+            ring::dump(out);
+            Ok(())
+        }
+        "net" => net(out),
+        "wifi-power" => wifi_power(args, out).await,
+        "wifi-latency" => wifi_latency(args, out).await,
+        "wifi-scan" => wifi_scan(out).await,
+        "crash" => crash_report(args, out),
+        _ => match find(name) {
+            Some(command) => (command.run)(args, out),
+            None => Err("unknown command, type 'help' for a list of commands"),
+        },
+    }
+}
+
+fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.lock(|commands| {
+        commands
+            .borrow()
+            .iter()
+            .find(|command| command.name == name)
+            .copied()
+    })
+}
+
+fn help(out: &mut impl Write) -> Result<(), &'static str> {
+    for (name, help) in BUILTIN_COMMANDS {
+        let _ = writeln!(out, "{name:<22} {help}");
+    }
+
+    COMMANDS.lock(|commands| {
+        for command in commands.borrow().iter() {
+            let _ = writeln!(out, "{:<22} {}", command.name, command.help);
+        }
+    });
+
+    Ok(())
+}
+
+fn status(out: &mut impl Write) -> Result<(), &'static str> {
+    let uptime = Instant::now().as_millis();
+    let _ = writeln!(out, "uptime: {}.{:03}s", uptime / 1000, uptime % 1000);
+    let _ = writeln!(out, "log filter: {}", logging::filter());
+    let _ = writeln!(
+        out,
+        "consecutive crashes: {}{}",
+        crash::consecutive_crashes(),
+        if panic_policy::in_safe_mode() {
+            " (safe mode)"
+        } else {
+            ""
+        }
+    );
+    if radio::control().is_some() {
+        let _ = writeln!(
+            out,
+            "radio: up, power mode {}",
+            radio::power_mode_name(radio::power_mode())
+        );
+    } else {
+        let _ = writeln!(out, "radio: down");
+    }
+
+    match network::stack() {
+        Some(stack) if stack.is_config_up() => {
+            let _ = writeln!(out, "network: up");
+        }
+        Some(stack) if stack.is_link_up() => {
+            let _ = writeln!(out, "network: link up, waiting for ip config");
+        }
+        _ => {
+            let _ = writeln!(out, "network: down");
+        }
+    }
+
+    Ok(())
+}
+
+async fn led(mut args: Args<'_>, out: &mut impl Write) -> Result<(), &'static str> {
+    let on = match args.next() {
+        Some("on") => true,
+        Some("off") => false,
+        _ => return Err("usage: led on|off"),
+    };
+
+    let control = radio::control().ok_or("radio not setup")?;
+    control.lock().await.gpio_set(0, on).await;
+    let _ = writeln!(out, "led {}", if on { "on" } else { "off" });
+    Ok(())
+}
+
+fn restart(reboot_type: u32, out: &mut impl Write) -> Result<(), &'static str> {
+    let _ = writeln!(out, "rebooting");
+    PENDING_REBOOT.lock(|pending| pending.set(Some(reboot_type)));
+    Ok(())
+}
+
+fn log_level(args: Args<'_>, out: &mut impl Write) -> Result<(), &'static str> {
+    // allow the filter to be typed with spaces, e.g. 'log-level info, cyw43=warn'
+    let mut spec: String<MAX_LINE_LEN> = String::new();
+    for arg in args {
+        let _ = spec.push_str(arg);
+    }
+
+    if !spec.is_empty() {
+        if let Err(e) = logging::set_filter(&spec) {
+            let _ = writeln!(out, "error: {e}");
+            return Err("usage: log-level [level][,module=level]...");
+        }
+    }
+
+    let _ = writeln!(out, "log filter: {}", logging::filter());
+    Ok(())
+}
+
+fn log_sink(mut args: Args<'_>, out: &mut impl Write) -> Result<(), &'static str> {
+    if let Some(name) = args.next() {
+        let level = args
+            .next()
+            .and_then(|level| level.parse::<LevelFilter>().ok())
+            .ok_or("usage: log-sink [name off|error|warn|info|debug|trace]")?;
+
+        if !logging::set_sink_level(name, level) {
+            return Err("unknown log sink");
+        }
+    }
+
+    for sink in logging::sink_info() {
+        let _ = write!(out, "{:<8} level={:<5} ", sink.name, sink.level);
+        let _ = match sink.overflow {
+            Overflow::Drop => write!(out, "overflow=drop"),
+            Overflow::Block(timeout) => write!(out, "overflow=block({}ms)", timeout.as_millis()),
+        };
+        let _ = writeln!(out, " dropped={}", sink.dropped);
+    }
+
+    Ok(())
+}
+
+async fn wifi_power(mut args: Args<'_>, out: &mut impl Write) -> Result<(), &'static str> {
+    if let Some(name) = args.next() {
+        let mode = radio::parse_power_mode(name).ok_or(
+            "usage: wifi-power [none|performance|powersave|throughput|aggressive|supersave]",
+        )?;
+        radio::set_power_mode(mode).await?;
+    }
+
+    let _ = writeln!(
+        out,
+        "wifi power mode: {}",
+        radio::power_mode_name(radio::power_mode())
+    );
+    Ok(())
+}
+
+async fn wifi_latency(mut args: Args<'_>, out: &mut impl Write) -> Result<(), &'static str> {
+    let target = match args.next() {
+        Some(ip) => Some(ip.parse().map_err(|_| "usage: wifi-latency [ip]")?),
+        None => None,
+    };
+
+    network::measure_latency(target, out).await
+}
+
+async fn wifi_scan(out: &mut impl Write) -> Result<(), &'static str> {
+    let control = radio::control().ok_or("radio not setup")?;
+    let results = radio::scan(control).await;
+
+    let _ = writeln!(out, "{:<32} {:<17} ch  rssi security", "ssid", "bssid");
+    for network in results.networks() {
+        let [a, b, c, d, e, f] = network.bssid;
+        let _ = writeln!(
+            out,
+            "{:<32} {a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{f:02x} {:>2} {:>5} {}",
+            if network.ssid.is_empty() {
+                "(hidden)"
+            } else {
+                network.ssid.as_str()
+            },
+            network.channel,
+            network.rssi,
+            network.security.as_str()
+        );
+    }
+    let _ = writeln!(out, "{} networks", results.networks().len());
+    Ok(())
+}
+
+fn net(out: &mut impl Write) -> Result<(), &'static str> {
+    let stack = network::stack().ok_or("network not setup")?;
+    let _ = writeln!(out, "hardware address: {}", stack.hardware_address());
+    let _ = writeln!(
+        out,
+        "link: {}",
+        if stack.is_link_up() { "up" } else { "down" }
+    );
+
+    match stack.config_v4() {
+        Some(config) => {
+            let _ = writeln!(out, "address: {}", config.address);
+            match config.gateway {
+                Some(gateway) => {
+                    let _ = writeln!(out, "gateway: {gateway}");
+                }
+                None => {
+                    let _ = writeln!(out, "gateway: none");
+                }
+            }
+            for dns in config.dns_servers.iter() {
+                let _ = writeln!(out, "dns: {dns}");
+            }
+        }
+        None => {
+            let _ = writeln!(out, "no ip config");
+        }
+    }
+
+    Ok(())
+}
+
+fn crash_report(mut args: Args<'_>, out: &mut impl Write) -> Result<(), &'static str> {
+    match args.next() {
+        None => match crash::last_crash() {
+            Some(report) => {
+                let _ = writeln!(out, "{report}");
+            }
+            None => {
+                let _ = writeln!(out, "no crash report");
+            }
+        },
+        Some("clear") => {
+            crash::clear();
+            let _ = writeln!(out, "crash report cleared");
+        }
+        Some(_) => return Err("usage: crash [clear]"),
+    }
+
+    Ok(())
+}
+
+// collects the output of a command so that it can be sent to the usb serial port in one go
+struct Reply {
+    text: String<MAX_REPLY_LEN>,
+}
+
+impl Reply {
+    fn new() -> Self {
+        Self {
+            text: String::new(),
+        }
+    }
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // serial terminals expect CRLF line endings, output that does not fit is dropped
+        for c in s.chars() {
+            if c == '\n' && !self.text.ends_with('\r') {
+                let _ = self.text.push('\r');
+            }
+            let _ = self.text.push(c);
+        }
+
+        Ok(())
+    }
+}